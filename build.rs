@@ -1,10 +1,10 @@
 extern crate bindgen;
 
-use std::fs;
-use std::path::PathBuf;
-
 #[cfg(feature = "generate-bindings")]
 fn generate_bindings() {
+    use std::fs;
+    use std::path::PathBuf;
+
     // Use bindgen to generate the bindings
     let bindings = bindgen::Builder::default()
         .header("wrapper.h")
@@ -0,0 +1,578 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use thiserror::Error;
+
+use dht22_reader::daemon::DaemonError;
+use dht22_reader::dht22::{self, Dht22Error};
+use dht22_reader::format::{Format, Formatter, Influx, Sensor, Tag};
+use dht22_reader::gpiod::{GpiodError, Offset, DEV_DIR};
+use dht22_reader::model::SensorModel;
+use dht22_reader::retry::RetryPolicy;
+use dht22_reader::sink::SinkSpec;
+use dht22_reader::trace::TraceError;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Reads temperature and humidity from a DHT22 sensor")]
+pub struct Cli {
+    /// GPIO chip the sensor is wired to, either as a path or a name like `gpiochip0`. Required
+    /// when `--line` is an offset; otherwise every chip is searched for the line.
+    #[arg(long, global = true)]
+    pub chip: Option<String>,
+
+    /// Line the sensor's data pin is on, either as an offset or a line name like `GPIO4`.
+    #[arg(long, global = true, default_value = "GPIO4")]
+    pub line: LineId,
+
+    /// Which sensor is wired up: dht11, dht21 (or am2301), dht22, am2302 or rht03. `auto` works
+    /// it out from the first few frames.
+    #[arg(long, global = true, default_value_t = ModelChoice::Known(SensorModel::default()))]
+    pub model: ModelChoice,
+
+    /// How long to wait for the next edge from the sensor before giving up on the frame.
+    #[arg(long, global = true, default_value_t = dht22::DEFAULT_TIMEOUT.as_micros() as u64)]
+    pub timeout_us: u64,
+
+    /// How many times to try each reading. Only failures the sensor might recover from, like a
+    /// bad checksum, are retried, backing off from the sensor's minimum interval.
+    #[arg(long, global = true, default_value_t = RetryPolicy::default().max_attempts,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub attempts: u32,
+
+    /// How readings are written: `human`, `json` (an object per line), `csv` (with a header) or
+    /// `influx` (InfluxDB line protocol). Applies to `read` and the daemon's `stdout` sink.
+    #[arg(long, global = true, default_value_t = Format::Human)]
+    pub format: Format,
+
+    /// Measurement InfluxDB points are written to, with `--format influx`.
+    #[arg(long, global = true, default_value_t = Influx::default().measurement)]
+    pub measurement: String,
+
+    /// A `key=value` tag for every InfluxDB point, alongside the chip, offset and model. Can be
+    /// given more than once.
+    #[arg(long = "tag", global = true, value_name = "KEY=VALUE")]
+    pub tags: Vec<Tag>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Take a reading and print it.
+    Read {
+        /// Number of readings to take.
+        #[arg(long, default_value_t = 1)]
+        count: u32,
+
+        /// Seconds to wait between readings. The sensor needs at least 2.
+        #[arg(long, default_value = "2", value_parser = parse_secs)]
+        interval: Duration,
+
+        /// Record the edges captured during each reading to a trace file, for `replay`.
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+
+        /// Write the line's waveform, start signal included, to a VCD file for viewing in GTKWave
+        /// or PulseView.
+        #[arg(long, value_name = "FILE")]
+        vcd: Option<PathBuf>,
+    },
+    /// Take a reading every `--interval` until stopped, publishing each to every `--sink`.
+    Daemon {
+        /// Seconds between readings. Anything under the sensor's minimum (1 for the DHT11, 2 for
+        /// the rest) is refused.
+        #[arg(long, default_value = "2", value_parser = parse_secs)]
+        interval: Duration,
+
+        /// Where to publish readings: `stdout`, or `jsonl:<path>` to append JSON lines to a file.
+        /// Can be given more than once.
+        #[arg(long = "sink", value_name = "SINK", default_value = "stdout")]
+        sinks: Vec<SinkSpec>,
+
+        /// Serve Prometheus metrics at `/metrics` on this address, eg `127.0.0.1:9198`.
+        #[arg(long, value_name = "ADDR")]
+        metrics: Option<SocketAddr>,
+
+        /// Name the sensor's metrics are labelled with, alongside its chip and line. Defaults to
+        /// `--line`.
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Decode the readings in a trace file recorded with `read --trace`.
+    Replay {
+        /// Trace file to decode.
+        trace: PathBuf,
+    },
+    /// List every line on every chip (or just `--chip`), and how each is set up.
+    Lines {
+        /// Print JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print whenever the sensor's line is requested, reconfigured or released by any process.
+    WatchLines {
+        /// Print each event as a line of JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Cli {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_micros(self.timeout_us)
+    }
+
+    /// Returns the retry policy for reading a `model` sensor, which may have been detected rather
+    /// than given.
+    pub fn retry_policy(&self, model: SensorModel) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.attempts,
+            ..RetryPolicy::for_model(model)
+        }
+    }
+
+    /// Returns the formatter for readings from `sensor`.
+    pub fn formatter(&self, sensor: Sensor) -> Formatter {
+        Formatter {
+            format: self.format,
+            sensor,
+            influx: Influx {
+                measurement: self.measurement.clone(),
+                tags: self.tags.clone(),
+            },
+        }
+    }
+
+    /// Returns the path of the chip to open, if one was given. Bare names are looked up under
+    /// `/dev`.
+    pub fn chip_path(&self) -> Option<PathBuf> {
+        let chip = self.chip.as_ref()?;
+        if chip.contains('/') {
+            Some(PathBuf::from(chip))
+        } else {
+            Some(Path::new(DEV_DIR).join(chip))
+        }
+    }
+}
+
+/// A line given on the command line, which is taken to be an offset if it is a number and a line
+/// name otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineId {
+    Offset(Offset),
+    Name(String),
+}
+
+impl FromStr for LineId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(offset) => LineId::Offset(offset),
+            Err(_) => LineId::Name(s.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for LineId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineId::Offset(offset) => write!(f, "{offset}"),
+            LineId::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// The sensor model given on the command line, or `auto` to detect it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelChoice {
+    Auto,
+    Known(SensorModel),
+}
+
+impl ModelChoice {
+    /// Returns how long the sensor needs between readings. Until an `auto` model is detected, it
+    /// could be any of them, so it gets the longest.
+    pub fn min_interval(self) -> Duration {
+        match self {
+            ModelChoice::Auto => SensorModel::ALL
+                .map(SensorModel::min_interval)
+                .into_iter()
+                .max()
+                .unwrap_or_default(),
+            ModelChoice::Known(model) => model.min_interval(),
+        }
+    }
+}
+
+impl FromStr for ModelChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(ModelChoice::Auto);
+        }
+        s.parse().map(ModelChoice::Known)
+    }
+}
+
+impl fmt::Display for ModelChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelChoice::Auto => write!(f, "auto"),
+            ModelChoice::Known(model) => write!(f, "{model}"),
+        }
+    }
+}
+
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    let secs: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|e| format!("{e}"))
+}
+
+/// Warns that a sensor read as `model` sends frames that look like a `suggested`'s.
+pub fn warn_model_mismatch(model: SensorModel, suggested: SensorModel) {
+    eprintln!(
+        "Warning: readings look like they're from a {suggested}, not a {model}. \
+         Try --model {suggested} or --model auto"
+    );
+}
+
+/// Why a command failed. Each class of failure exits with its own code, so scripts can tell a
+/// wiring problem from a sensor that just needs to be asked again.
+#[derive(Error, Debug)]
+pub enum Failure {
+    #[error("{0}")]
+    Chip(GpiodError),
+    #[error("Failed to find line {0}: {1}")]
+    Line(String, GpiodError),
+    #[error("Failed to request line: {0}")]
+    Request(GpiodError),
+    #[error("Failed to read sensor: {0}")]
+    Sensor(#[from] Dht22Error),
+    #[error("Failed to read line info: {0}")]
+    LineInfo(GpiodError),
+    #[error("Failed to write output: {0}")]
+    Output(#[from] std::io::Error),
+    #[error("Failed to read or write trace {0}: {1}")]
+    Trace(String, TraceError),
+    #[error("Failed to write waveform {0}: {1}")]
+    Waveform(String, std::io::Error),
+    #[error("Failed to open sink {0}: {1}")]
+    Sink(String, std::io::Error),
+    #[error("Failed to set up signal handling: {0}")]
+    Signals(std::io::Error),
+    #[error("Failed to serve metrics on {0}: {1}")]
+    Metrics(SocketAddr, std::io::Error),
+}
+
+impl From<DaemonError> for Failure {
+    fn from(e: DaemonError) -> Self {
+        match e {
+            DaemonError::Sensor(e) => Failure::Sensor(e),
+            DaemonError::Sink(e) => Failure::Output(e),
+        }
+    }
+}
+
+impl Failure {
+    /// Prints the failure of a reading that took `attempts` attempts.
+    pub fn report(&self, attempts: u32) {
+        match attempts {
+            1 => eprintln!("{self}"),
+            _ => eprintln!("{self} (gave up after {attempts} attempts)"),
+        }
+    }
+
+    /// Returns the process exit code for the failure. 1 is left for panics and 2 for usage
+    /// errors, which clap reports itself.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Failure::Chip(..) => 3,
+            Failure::Line(..) => 4,
+            Failure::Request(_) | Failure::LineInfo(_) | Failure::Sensor(Dht22Error::Gpiod(_)) => 5,
+            Failure::Sensor(Dht22Error::ShortFrame(_)) => 6,
+            Failure::Sensor(Dht22Error::Checksum { .. }) => 7,
+            Failure::Output(_) | Failure::Waveform(..) | Failure::Sink(..) => 8,
+            Failure::Trace(..) => 9,
+            Failure::Signals(_) => 10,
+            Failure::Metrics(..) => 11,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    #[test]
+    fn test_formatter() {
+        let cli = Cli::try_parse_from([
+            "dht22_reader",
+            "read",
+            "--format",
+            "influx",
+            "--measurement",
+            "climate",
+            "--tag",
+            "room=attic",
+            "--tag",
+            "floor=2",
+        ])
+        .unwrap();
+        let sensor = Sensor {
+            chip: "/dev/gpiochip0".to_string(),
+            offset: 4,
+            model: SensorModel::Dht22,
+        };
+        let formatter = cli.formatter(sensor.clone());
+        assert_eq!(formatter.format, Format::Influx);
+        assert_eq!(formatter.sensor, sensor);
+        assert_eq!(formatter.influx.measurement, "climate");
+        assert_eq!(formatter.influx.tags.len(), 2);
+        assert_eq!(formatter.influx.tags[1].key, "floor");
+    }
+
+    #[test]
+    fn test_format_invalid() {
+        assert!(Cli::try_parse_from(["dht22_reader", "read", "--format", "xml"]).is_err());
+        assert!(Cli::try_parse_from(["dht22_reader", "read", "--tag", "room"]).is_err());
+    }
+
+    #[test]
+    fn test_defaults() {
+        let cli = Cli::try_parse_from(["dht22_reader", "read"]).unwrap();
+        assert_eq!(cli.chip_path(), None);
+        assert_eq!(cli.line, LineId::Name("GPIO4".to_string()));
+        assert_eq!(cli.timeout(), Duration::from_millis(1));
+        assert_eq!(cli.model, ModelChoice::Known(SensorModel::Dht22));
+        assert_eq!(cli.format, Format::Human);
+        assert_eq!(cli.measurement, "dht22");
+        assert!(cli.tags.is_empty());
+        assert_eq!(cli.retry_policy(SensorModel::Dht22), RetryPolicy::default());
+        assert!(matches!(
+            cli.command,
+            Command::Read { count: 1, interval, trace: None, vcd: None }
+                if interval == Duration::from_secs(2)
+        ));
+    }
+
+    #[test]
+    fn test_global_options_after_subcommand() {
+        let cli = Cli::try_parse_from([
+            "dht22_reader",
+            "read",
+            "--chip",
+            "/dev/gpiochip4",
+            "--line",
+            "GPIO17",
+            "--timeout-us",
+            "500",
+            "--count",
+            "3",
+            "--interval",
+            "2.5",
+        ])
+        .unwrap();
+        assert_eq!(cli.chip_path(), Some(PathBuf::from("/dev/gpiochip4")));
+        assert_eq!(cli.line, LineId::Name("GPIO17".to_string()));
+        assert_eq!(cli.timeout(), Duration::from_micros(500));
+        assert!(matches!(
+            cli.command,
+            Command::Read { count: 3, interval, .. } if interval == Duration::from_millis(2500)
+        ));
+    }
+
+    #[test_case("gpiochip4", "/dev/gpiochip4"; "name")]
+    #[test_case("/dev/gpiochip4", "/dev/gpiochip4"; "path")]
+    #[test_case("./gpiochip4", "./gpiochip4"; "relative path")]
+    #[test]
+    fn test_chip_path(chip: &str, expected: &str) {
+        let cli = Cli::try_parse_from(["dht22_reader", "read", "--chip", chip, "--line", "4"]);
+        let cli = cli.unwrap();
+        assert_eq!(cli.line, LineId::Offset(4));
+        assert_eq!(cli.chip_path(), Some(PathBuf::from(expected)));
+    }
+
+    #[test_case("-1"; "negative")]
+    #[test_case("soon"; "not a number")]
+    #[test]
+    fn test_invalid_interval(interval: &str) {
+        let result = Cli::try_parse_from(["dht22_reader", "read", "--interval", interval]);
+        assert!(result.is_err());
+    }
+
+    #[test_case(&["lines"], false; "table")]
+    #[test_case(&["lines", "--json"], true; "json")]
+    #[test]
+    fn test_lines(args: &[&str], expected: bool) {
+        let cli = Cli::try_parse_from(["dht22_reader"].iter().chain(args)).unwrap();
+        assert!(matches!(cli.command, Command::Lines { json } if json == expected));
+    }
+
+    #[test]
+    fn test_watch_lines() {
+        let cli = Cli::try_parse_from(["dht22_reader", "watch-lines", "--line", "GPIO17"]);
+        let cli = cli.unwrap();
+        assert_eq!(cli.line, LineId::Name("GPIO17".to_string()));
+        assert!(matches!(cli.command, Command::WatchLines { json: false }));
+    }
+
+    #[test]
+    fn test_read_trace() {
+        let cli = Cli::try_parse_from(["dht22_reader", "read", "--trace", "failing.trace"]);
+        let trace = Some(PathBuf::from("failing.trace"));
+        assert!(matches!(cli.unwrap().command, Command::Read { trace: t, .. } if t == trace));
+    }
+
+    #[test]
+    fn test_read_vcd() {
+        let cli = Cli::try_parse_from(["dht22_reader", "read", "--vcd", "handshake.vcd"]);
+        let vcd = Some(PathBuf::from("handshake.vcd"));
+        assert!(matches!(cli.unwrap().command, Command::Read { vcd: v, .. } if v == vcd));
+    }
+
+    #[test]
+    fn test_replay() {
+        let cli = Cli::try_parse_from(["dht22_reader", "replay", "failing.trace"]).unwrap();
+        assert!(
+            matches!(cli.command, Command::Replay { trace } if trace == Path::new("failing.trace"))
+        );
+        assert!(Cli::try_parse_from(["dht22_reader", "replay"]).is_err());
+    }
+
+    #[test]
+    fn test_daemon_defaults() {
+        let cli = Cli::try_parse_from(["dht22_reader", "daemon"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Daemon { interval, sinks, metrics: None, name: None }
+                if interval == Duration::from_secs(2) && sinks == [SinkSpec::Stdout]
+        ));
+    }
+
+    #[test]
+    fn test_daemon_sinks() {
+        let cli = Cli::try_parse_from([
+            "dht22_reader",
+            "daemon",
+            "--interval",
+            "60",
+            "--sink",
+            "stdout",
+            "--sink",
+            "jsonl:/var/log/dht22.jsonl",
+        ]);
+        let Command::Daemon {
+            interval, sinks, ..
+        } = cli.unwrap().command
+        else {
+            panic!("expected daemon");
+        };
+        assert_eq!(interval, Duration::from_secs(60));
+        assert_eq!(
+            sinks,
+            [
+                SinkSpec::Stdout,
+                SinkSpec::JsonLines(PathBuf::from("/var/log/dht22.jsonl"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_daemon_metrics() {
+        let cli = Cli::try_parse_from([
+            "dht22_reader",
+            "daemon",
+            "--metrics",
+            "127.0.0.1:9198",
+            "--name",
+            "attic",
+        ]);
+        let Command::Daemon { metrics, name, .. } = cli.unwrap().command else {
+            panic!("expected daemon");
+        };
+        assert_eq!(metrics, Some("127.0.0.1:9198".parse().unwrap()));
+        assert_eq!(name.as_deref(), Some("attic"));
+
+        let result = Cli::try_parse_from(["dht22_reader", "daemon", "--metrics", "localhost"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_daemon_invalid_sink() {
+        let result = Cli::try_parse_from(["dht22_reader", "daemon", "--sink", "syslog"]);
+        assert!(result.is_err());
+    }
+
+    #[test_case(&[], ModelChoice::Known(SensorModel::Dht22); "default")]
+    #[test_case(&["--model", "dht11"], ModelChoice::Known(SensorModel::Dht11); "known")]
+    #[test_case(&["--model", "auto"], ModelChoice::Auto; "auto")]
+    #[test]
+    fn test_model(args: &[&str], expected: ModelChoice) {
+        let cli = Cli::try_parse_from(["dht22_reader", "read"].iter().chain(args)).unwrap();
+        assert_eq!(cli.model, expected);
+    }
+
+    #[test]
+    fn test_model_invalid() {
+        assert!(Cli::try_parse_from(["dht22_reader", "read", "--model", "dht33"]).is_err());
+    }
+
+    #[test_case(ModelChoice::Known(SensorModel::Dht11), 1; "dht11")]
+    #[test_case(ModelChoice::Known(SensorModel::Dht22), 2; "dht22")]
+    #[test_case(ModelChoice::Auto, 2; "auto")]
+    #[test]
+    fn test_model_min_interval(model: ModelChoice, secs: u64) {
+        assert_eq!(model.min_interval(), Duration::from_secs(secs));
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let cli = Cli::try_parse_from(["dht22_reader", "read"]).unwrap();
+        let policy = cli.retry_policy(SensorModel::Dht11);
+        assert_eq!(policy.cooldown, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_attempts() {
+        let cli = Cli::try_parse_from(["dht22_reader", "read", "--attempts", "5"]).unwrap();
+        assert_eq!(cli.retry_policy(SensorModel::Dht22).max_attempts, 5);
+        assert!(Cli::try_parse_from(["dht22_reader", "read", "--attempts", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_subcommand_required() {
+        assert!(Cli::try_parse_from(["dht22_reader"]).is_err());
+    }
+
+    fn busy() -> std::io::Error {
+        std::io::ErrorKind::ResourceBusy.into()
+    }
+
+    #[test_case(Failure::Chip(GpiodError::OpenChip { path: "/dev/gpiochip9".into(), source: busy() }), 3; "chip")]
+    #[test_case(Failure::Line("GPIO99".to_string(), GpiodError::LineNotFound("GPIO99".to_string())), 4; "line")]
+    #[test_case(Failure::Request(GpiodError::RequestLines { chip: "/dev/gpiochip0".into(), offsets: vec![4], source: busy() }), 5; "request")]
+    #[test_case(Failure::Sensor(Dht22Error::Gpiod(GpiodError::ReadEdgeEvents { chip: "/dev/gpiochip0".into(), source: busy() })), 5; "gpio during read")]
+    #[test_case(Failure::Sensor(Dht22Error::ShortFrame(12)), 6; "short frame")]
+    #[test_case(Failure::Sensor(Dht22Error::Checksum { expected: 1, actual: 2 }), 7; "checksum")]
+    #[test_case(Failure::LineInfo(GpiodError::GetLineInfo { chip: "/dev/gpiochip0".into(), offset: 3, source: busy() }), 5; "line info")]
+    #[test_case(Failure::Output(std::io::ErrorKind::BrokenPipe.into()), 8; "output")]
+    #[test_case(Failure::Waveform("x.vcd".to_string(), std::io::ErrorKind::StorageFull.into()), 8; "waveform")]
+    #[test_case(Failure::Sink("jsonl:/x".to_string(), std::io::ErrorKind::PermissionDenied.into()), 8; "sink")]
+    #[test_case(Failure::Signals(std::io::ErrorKind::Other.into()), 10; "signals")]
+    #[test_case(Failure::Metrics("127.0.0.1:9198".parse().unwrap(), std::io::ErrorKind::AddrInUse.into()), 11; "metrics")]
+    #[test_case(Failure::Trace("x.trace".to_string(), TraceError::NotATrace), 9; "trace")]
+    #[test_case(DaemonError::Sink(std::io::ErrorKind::BrokenPipe.into()).into(), 8; "daemon sink")]
+    #[test]
+    fn test_exit_codes(failure: Failure, code: u8) {
+        assert_eq!(failure.exit_code(), code);
+    }
+}
@@ -0,0 +1,420 @@
+//! A loop taking readings on an interval, for as long as a sensor is to be watched.
+
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::dht22::{self, Dht22Error};
+use crate::metrics::Metrics;
+use crate::model::{MismatchCheck, SensorModel};
+use crate::pin::SensorPin;
+use crate::retry::RetryPolicy;
+use crate::sink::{Sample, Sink};
+
+/// How the daemon takes readings: from which model of sensor, how often, and how hard it tries.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub model: SensorModel,
+    pub policy: RetryPolicy,
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+/// Why the daemon stopped before it was asked to.
+#[derive(Error, Debug)]
+pub enum DaemonError {
+    #[error(transparent)]
+    Sensor(#[from] Dht22Error),
+    #[error(transparent)]
+    Sink(#[from] io::Error),
+}
+
+/// Something worth telling whoever runs the daemon, which it carries on after.
+#[derive(Debug)]
+pub enum Event {
+    /// The frames look like they're from a `suggested` sensor, not the model being read.
+    ModelMismatch { suggested: SensorModel },
+    /// A reading failed every attempt it was allowed, and was skipped.
+    ReadingFailed { error: Dht22Error, attempts: u32 },
+}
+
+/// Takes a reading from the sensor on `pin` every `settings.interval` until anything arrives on
+/// `stop` (or its sender goes away), publishing each one to every sink and recording every attempt
+/// in `metrics`.
+///
+/// The pin is held for the whole time, so nothing else can grab the line between readings.
+/// Readings are spaced from the start of one to the start of the next, so a slow read doesn't
+/// make them drift, though retries can push the next reading back to keep `interval` clear of the
+/// last attempt. A reading that failed its retries is passed to `on_event` and skipped, but a
+/// failure that retrying can't fix, like losing the line, or a sink failing stops the daemon. If
+/// the frames look like they're from a different model, that's passed on once.
+///
+/// Waits between retries are cut short by `stop` too, so stopping is never held up by a sensor
+/// that's backing off.
+pub fn run(
+    pin: &mut impl SensorPin,
+    settings: &Settings,
+    sinks: &mut [Box<dyn Sink>],
+    metrics: &Metrics,
+    stop: &Receiver<()>,
+    mut on_event: impl FnMut(Event),
+) -> Result<(), DaemonError> {
+    let Settings {
+        model,
+        policy,
+        interval,
+        timeout,
+    } = *settings;
+    let mut check = MismatchCheck::new(model);
+    let mut next = Instant::now();
+    loop {
+        let mut stopped = false;
+        let outcome = policy.retry_until(
+            || {
+                let started = Instant::now();
+                let result = dht22::read_frame(pin, model.start_signal(), timeout);
+                if let Some(suggested) = result.as_ref().ok().and_then(|f| check.observe(f)) {
+                    on_event(Event::ModelMismatch { suggested });
+                }
+                let result = result.map(|frame| model.decode(&frame));
+                metrics.observe_attempt(&result, started.elapsed());
+                result
+            },
+            |delay| {
+                stopped = !matches!(stop.recv_timeout(delay), Err(RecvTimeoutError::Timeout));
+                !stopped
+            },
+        );
+        metrics.observe_reading(&outcome);
+        if stopped {
+            return Ok(());
+        }
+        match outcome.result {
+            Ok(reading) => {
+                let sample = Sample::now(reading, outcome.attempts);
+                for sink in sinks.iter_mut() {
+                    sink.publish(&sample)?;
+                }
+            }
+            Err(e) if !e.is_transient() => return Err(e.into()),
+            Err(error) => on_event(Event::ReadingFailed {
+                error,
+                attempts: outcome.attempts,
+            }),
+        }
+
+        next = (next + interval).max(outcome.last_attempt + interval);
+        match stop.recv_timeout(next.saturating_duration_since(Instant::now())) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht22::{Reading, DEFAULT_TIMEOUT};
+    use crate::format::Sensor;
+    use crate::gpiod::GpiodError;
+    use crate::pin::CapturedEdge;
+    use crate::sim::SimulatedDht22;
+    use std::sync::mpsc::{self, Sender};
+
+    const READING: Reading = Reading {
+        humidity_percent: 65.2,
+        temperature_celsius: 35.1,
+    };
+
+    const INTERVAL: Duration = Duration::from_millis(20);
+
+    const SETTINGS: Settings = Settings {
+        model: SensorModel::Dht22,
+        // Retries wait out the sensor's cooldown, which would make for slow tests.
+        policy: RetryPolicy {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            cooldown: Duration::ZERO,
+        },
+        interval: INTERVAL,
+        timeout: DEFAULT_TIMEOUT,
+    };
+
+    fn metrics() -> Metrics {
+        let sensor = Sensor {
+            chip: "/dev/gpiochip0".to_string(),
+            offset: 4,
+            model: SensorModel::Dht22,
+        };
+        Metrics::new(&sensor, "test")
+    }
+
+    // Passes samples on, and stops the daemon once it has passed on `limit` of them.
+    struct StoppingSink {
+        samples: Sender<Sample>,
+        sent: usize,
+        limit: usize,
+        stop: Sender<()>,
+    }
+
+    impl Sink for StoppingSink {
+        fn publish(&mut self, sample: &Sample) -> io::Result<()> {
+            self.samples.send(*sample).unwrap();
+            self.sent += 1;
+            if self.sent == self.limit {
+                self.stop.send(()).unwrap();
+            }
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    impl Sink for FailingSink {
+        fn publish(&mut self, _: &Sample) -> io::Result<()> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+    }
+
+    // A pin whose line has been taken away, so every read fails for good.
+    struct LostPin;
+
+    impl SensorPin for LostPin {
+        fn drive_low(&mut self) -> Result<(), GpiodError> {
+            Err(GpiodError::Reconfigure {
+                chip: "/dev/gpiochip0".into(),
+                offsets: vec![4],
+                source: io::ErrorKind::ResourceBusy.into(),
+            })
+        }
+
+        fn release(&mut self) -> Result<(), GpiodError> {
+            Ok(())
+        }
+
+        fn wait_edges(&mut self, _: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+            Ok(vec![])
+        }
+
+        fn now(&self) -> Result<Duration, GpiodError> {
+            Ok(Duration::ZERO)
+        }
+    }
+
+    // A sensor that doesn't answer its first `failures` start signals, then does.
+    struct FlakyPin {
+        silent: SimulatedDht22,
+        working: SimulatedDht22,
+        failures: usize,
+    }
+
+    impl FlakyPin {
+        fn pin(&mut self) -> &mut SimulatedDht22 {
+            match self.failures {
+                0 => &mut self.working,
+                _ => &mut self.silent,
+            }
+        }
+    }
+
+    impl SensorPin for FlakyPin {
+        fn drive_low(&mut self) -> Result<(), GpiodError> {
+            self.pin().drive_low()
+        }
+
+        fn release(&mut self) -> Result<(), GpiodError> {
+            let result = self.pin().release();
+            self.failures = self.failures.saturating_sub(1);
+            result
+        }
+
+        fn wait_edges(&mut self, timeout: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+            self.pin().wait_edges(timeout)
+        }
+
+        fn now(&self) -> Result<Duration, GpiodError> {
+            match self.failures {
+                0 => self.working.now(),
+                _ => self.silent.now(),
+            }
+        }
+    }
+
+    // Runs the daemon until the sink has `limit` samples, returning them.
+    fn run_until(pin: &mut impl SensorPin, limit: usize) -> Vec<Sample> {
+        let (stop, stopped) = mpsc::channel();
+        let (samples, published) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(StoppingSink {
+            samples,
+            sent: 0,
+            limit,
+            stop,
+        })];
+        run(pin, &SETTINGS, &mut sinks, &metrics(), &stopped, |_| {}).unwrap();
+        published.try_iter().collect()
+    }
+
+    #[test]
+    fn test_run() {
+        let started = Instant::now();
+        let samples = run_until(&mut SimulatedDht22::new(READING), 3);
+        assert_eq!(samples.len(), 3);
+        assert!(samples.iter().all(|s| s.reading == READING));
+        // Three readings are two intervals apart, however long they take.
+        assert!(started.elapsed() >= 2 * INTERVAL);
+    }
+
+    #[test]
+    fn test_run_skips_failed_readings() {
+        // Nothing is published, so stopping is up to the sender going away.
+        let (stop, stopped) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(FailingSink)];
+        let mut pin = SimulatedDht22::new(READING).without_response();
+        let mut events = vec![];
+        drop(stop);
+        run(&mut pin, &SETTINGS, &mut sinks, &metrics(), &stopped, |e| {
+            events.push(e)
+        })
+        .unwrap();
+        assert!(matches!(
+            events[..],
+            [Event::ReadingFailed {
+                error: Dht22Error::ShortFrame(0),
+                attempts: 1
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_run_reports_model_mismatch_once() {
+        let (stop, stopped) = mpsc::channel();
+        let (samples, _published) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(StoppingSink {
+            samples,
+            sent: 0,
+            limit: 2,
+            stop,
+        })];
+        // A DHT11's frame, from a sensor that answers the DHT22's start signal.
+        let mut pin = SimulatedDht22::from_frame([0x2D, 0x00, 0x15, 0x03, 0x45]);
+        let mut events = vec![];
+        run(&mut pin, &SETTINGS, &mut sinks, &metrics(), &stopped, |e| {
+            events.push(e)
+        })
+        .unwrap();
+        assert!(matches!(
+            events[..],
+            [Event::ModelMismatch {
+                suggested: SensorModel::Dht11
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_run_records_metrics() {
+        let (stop, stopped) = mpsc::channel();
+        let (samples, _published) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(StoppingSink {
+            samples,
+            sent: 0,
+            limit: 1,
+            stop,
+        })];
+        let mut pin = FlakyPin {
+            silent: SimulatedDht22::new(READING).without_response(),
+            working: SimulatedDht22::new(READING),
+            failures: 1,
+        };
+        let metrics = metrics();
+        run(
+            &mut pin,
+            &Settings {
+                policy: RetryPolicy {
+                    max_attempts: 2,
+                    ..SETTINGS.policy
+                },
+                ..SETTINGS
+            },
+            &mut sinks,
+            &metrics,
+            &stopped,
+            |_| {},
+        )
+        .unwrap();
+
+        let text = metrics.encode();
+        let value = |name: &str| {
+            let line = text.lines().find(|l| l.starts_with(name)).unwrap();
+            line.rsplit(' ').next().unwrap().to_string()
+        };
+        assert_eq!(value("dht22_timeouts_total{"), "1");
+        assert_eq!(value("dht22_retries_total{"), "1");
+        assert_eq!(value("dht22_reads_total{"), "1");
+        assert_eq!(value("dht22_read_duration_seconds_count{"), "2");
+    }
+
+    #[test]
+    fn test_run_stops_on_sink_failure() {
+        let (_stop, stopped) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(FailingSink)];
+        let result = run(
+            &mut SimulatedDht22::new(READING),
+            &SETTINGS,
+            &mut sinks,
+            &metrics(),
+            &stopped,
+            |_| {},
+        );
+        assert!(
+            matches!(result, Err(DaemonError::Sink(e)) if e.kind() == io::ErrorKind::BrokenPipe)
+        );
+    }
+
+    #[test]
+    fn test_run_stops_on_fatal_error() {
+        let (_stop, stopped) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![];
+        let result = run(
+            &mut LostPin,
+            &SETTINGS,
+            &mut sinks,
+            &metrics(),
+            &stopped,
+            |_| {},
+        );
+        assert!(matches!(
+            result,
+            Err(DaemonError::Sensor(Dht22Error::Gpiod(_)))
+        ));
+    }
+
+    #[test]
+    fn test_stop_cuts_retries_short() {
+        let (stop, stopped) = mpsc::channel();
+        let mut sinks: Vec<Box<dyn Sink>> = vec![];
+        let mut pin = SimulatedDht22::new(READING).without_response();
+        let settings = Settings {
+            policy: RetryPolicy {
+                max_attempts: 3,
+                ..RetryPolicy::default()
+            },
+            ..SETTINGS
+        };
+        let started = Instant::now();
+        stop.send(()).unwrap();
+        run(
+            &mut pin,
+            &settings,
+            &mut sinks,
+            &metrics(),
+            &stopped,
+            |_| {},
+        )
+        .unwrap();
+        // The first retry would have waited out the sensor's two second cooldown.
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
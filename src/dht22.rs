@@ -0,0 +1,473 @@
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::gpiod::{EdgeKind, GpiodError};
+use crate::model::SensorModel;
+use crate::pin::{CapturedEdge, SensorPin};
+
+// A frame is 5 bytes: humidity high/low, temperature high/low and a checksum.
+pub const FRAME_BITS: usize = 40;
+
+// After the ~50us low that starts each bit, the sensor holds the line high for 26-28us to send a 0
+// and ~70us to send a 1. Anything longer than the midpoint is a 1.
+const BIT_THRESHOLD: Duration = Duration::from_micros(50);
+
+/// How long to wait for the next edge before deciding the frame is over (or never started). The
+/// longest the sensor leaves the line alone mid-frame is its 80us ack.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1);
+
+// A full response is 84 edges: the ack, a low/high pair per bit, then the sensor letting go of
+// the line. Leave room for noise, but stop reading if the line never settles.
+const MAX_EDGES: usize = 256;
+
+#[derive(Error, Debug)]
+pub enum Dht22Error {
+    #[error("Expected {FRAME_BITS} bits from the sensor but got {0}")]
+    ShortFrame(usize),
+    #[error("Checksum mismatch: frame says {expected:#04x} but data sums to {actual:#04x}")]
+    Checksum { expected: u8, actual: u8 },
+    #[error(transparent)]
+    Gpiod(#[from] GpiodError),
+}
+
+impl Dht22Error {
+    /// Whether asking the sensor again might work. Garbled or missing frames are a fact of life
+    /// with the DHT22, but a GPIO operation failing means something is wrong with the setup. A
+    /// sensor that doesn't respond in time is a [`Dht22Error::ShortFrame`] with no bits.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Dht22Error::ShortFrame(_) | Dht22Error::Checksum { .. } => true,
+            Dht22Error::Gpiod(_) => false,
+        }
+    }
+}
+
+/// A single decoded reading from the sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Reading {
+    pub humidity_percent: f32,
+    pub temperature_celsius: f32,
+}
+
+impl Reading {
+    /// Converts a checksum-validated frame in the DHT22's format into a reading. Both values are
+    /// sent in tenths, with the top bit of the temperature used as a sign bit rather than two's
+    /// complement. See [`SensorModel::decode`] for the other models.
+    pub fn from_frame(frame: &[u8; 5]) -> Self {
+        let humidity = u16::from_be_bytes([frame[0], frame[1]]);
+        let temperature = u16::from_be_bytes([frame[2] & 0x7F, frame[3]]);
+        let sign = if frame[2] & 0x80 != 0 { -1.0 } else { 1.0 };
+
+        Reading {
+            humidity_percent: f32::from(humidity) / 10.0,
+            temperature_celsius: sign * f32::from(temperature) / 10.0,
+        }
+    }
+
+    /// Encodes the reading as the sensor would send it, rounded to the nearest tenth and with a
+    /// valid checksum. The inverse of [`Reading::from_frame`].
+    pub fn to_frame(self) -> [u8; 5] {
+        let humidity = (self.humidity_percent * 10.0).round() as u16;
+        let temperature = (self.temperature_celsius.abs() * 10.0).round() as u16 & 0x7FFF;
+        let [h_hi, h_lo] = humidity.to_be_bytes();
+        let [t_hi, t_lo] = temperature.to_be_bytes();
+        let sign = if self.temperature_celsius < 0.0 {
+            0x80
+        } else {
+            0
+        };
+
+        let mut frame = [h_hi, h_lo, t_hi | sign, t_lo, 0];
+        frame[4] = frame[..4].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        frame
+    }
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Humidity: {:.1}%, Temperature: {:.1}°C",
+            self.humidity_percent, self.temperature_celsius
+        )
+    }
+}
+
+/// Turns the widths of the 40 high pulses sent by the sensor into the raw 5 byte frame, most
+/// significant bit first. Pulses beyond the first 40 are ignored.
+pub fn decode_frame(pulses: &[Duration]) -> Result<[u8; 5], Dht22Error> {
+    if pulses.len() < FRAME_BITS {
+        return Err(Dht22Error::ShortFrame(pulses.len()));
+    }
+
+    let mut frame = [0u8; 5];
+    for (i, pulse) in pulses.iter().take(FRAME_BITS).enumerate() {
+        if *pulse > BIT_THRESHOLD {
+            frame[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+
+    let actual = frame[..4].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    if actual != frame[4] {
+        return Err(Dht22Error::Checksum {
+            expected: frame[4],
+            actual,
+        });
+    }
+
+    Ok(frame)
+}
+
+/// Decodes the widths of the 40 high pulses sent by a `model` sensor into a reading.
+pub fn decode(pulses: &[Duration], model: SensorModel) -> Result<Reading, Dht22Error> {
+    decode_frame(pulses).map(|frame| model.decode(&frame))
+}
+
+/// Takes a reading from the `model` sensor on `pin`. The frame is taken to be over once no edge
+/// has been seen for `timeout`.
+///
+/// The host pulls the line low to wake the sensor, then lets go of it and collects the edges the
+/// sensor sends back. Pulse widths are measured between the pin's timestamps rather than by
+/// polling, so with a kernel-timestamped pin they are unaffected by how promptly we get scheduled.
+pub fn read(
+    pin: &mut impl SensorPin,
+    model: SensorModel,
+    timeout: Duration,
+) -> Result<Reading, Dht22Error> {
+    read_frame(pin, model.start_signal(), timeout).map(|frame| model.decode(&frame))
+}
+
+/// Takes a raw frame from the sensor on `pin`, holding the line low for `start_signal` to ask for
+/// it. Useful when the model isn't known yet, as every model answers the longest start signal.
+pub fn read_frame(
+    pin: &mut impl SensorPin,
+    start_signal: Duration,
+    timeout: Duration,
+) -> Result<[u8; 5], Dht22Error> {
+    pin.drive_low()?;
+    thread::sleep(start_signal);
+    pin.release()?;
+
+    let mut edges = Vec::with_capacity(MAX_EDGES);
+    while edges.len() < MAX_EDGES {
+        let batch = pin.wait_edges(timeout)?;
+        if batch.is_empty() {
+            break;
+        }
+        edges.extend(batch);
+    }
+
+    captured_frame(&edges)
+}
+
+/// Measures every high pulse in a sequence of edges, from each rising edge to the falling edge
+/// that follows it. A rising edge with no falling edge after it is not a complete pulse.
+pub fn high_pulses(edges: &[(EdgeKind, Duration)]) -> Vec<Duration> {
+    edges
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [(EdgeKind::Rising, rose), (EdgeKind::Falling, fell)] => {
+                Some(fell.saturating_sub(*rose))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Decodes a reading from the edges seen on the line after the start signal. The sensor's ack is
+/// also a high pulse, and may or may not have been caught depending on how quickly the line was
+/// switched to an input, so the frame is taken to be the last 40 high pulses.
+pub fn decode_edges(
+    edges: &[(EdgeKind, Duration)],
+    model: SensorModel,
+) -> Result<Reading, Dht22Error> {
+    edges_frame(edges).map(|frame| model.decode(&frame))
+}
+
+/// Recovers the raw frame from the edges seen on the line, as [`decode_edges`] does before
+/// converting it.
+pub fn edges_frame(edges: &[(EdgeKind, Duration)]) -> Result<[u8; 5], Dht22Error> {
+    let pulses = high_pulses(edges);
+    let start = pulses.len().saturating_sub(FRAME_BITS);
+    decode_frame(&pulses[start..])
+}
+
+/// Decodes a reading from the edges captured from a pin, as [`decode_edges`] does.
+pub fn decode_captured(edges: &[CapturedEdge], model: SensorModel) -> Result<Reading, Dht22Error> {
+    captured_frame(edges).map(|frame| model.decode(&frame))
+}
+
+/// Recovers the raw frame from the edges captured from a pin, as [`edges_frame`] does.
+pub fn captured_frame(edges: &[CapturedEdge]) -> Result<[u8; 5], Dht22Error> {
+    let edges: Vec<_> = edges.iter().map(|e| (e.kind, e.timestamp)).collect();
+    edges_frame(&edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    const ZERO: Duration = Duration::from_micros(27);
+    const ONE: Duration = Duration::from_micros(70);
+
+    fn pulses(frame: [u8; 5]) -> Vec<Duration> {
+        (0..FRAME_BITS)
+            .map(|i| {
+                if frame[i / 8] & (0x80 >> (i % 8)) != 0 {
+                    ONE
+                } else {
+                    ZERO
+                }
+            })
+            .collect()
+    }
+
+    #[test_case([0x02, 0x8C, 0x01, 0x5F, 0xEE], 65.2, 35.1; "positive temperature")]
+    #[test_case([0x01, 0xF4, 0x80, 0x65, 0xDA], 50.0, -10.1; "negative temperature")]
+    #[test_case([0x03, 0xE8, 0x80, 0x00, 0x6B], 100.0, 0.0; "negative zero")]
+    #[test_case([0x00, 0x00, 0x00, 0x00, 0x00], 0.0, 0.0; "all zero")]
+    #[test]
+    fn test_decode(frame: [u8; 5], humidity: f32, temperature: f32) {
+        let reading = decode(&pulses(frame), SensorModel::Dht22).unwrap();
+        assert_eq!(reading.humidity_percent, humidity);
+        assert_eq!(reading.temperature_celsius, temperature);
+    }
+
+    #[test_case([0x02, 0x8C, 0x01, 0x5F, 0xEE]; "positive temperature")]
+    #[test_case([0x01, 0xF4, 0x80, 0x65, 0xDA]; "negative temperature")]
+    #[test_case([0xFF, 0xFF, 0x00, 0x02, 0x00]; "checksum wraps")]
+    #[test]
+    fn test_frame_round_trip(frame: [u8; 5]) {
+        assert_eq!(Reading::from_frame(&frame).to_frame(), frame);
+    }
+
+    // The line being taken out from under us, which trying again won't fix.
+    fn reconfigure_failed() -> GpiodError {
+        GpiodError::Reconfigure {
+            chip: "/dev/gpiochip0".into(),
+            offsets: vec![4],
+            source: std::io::ErrorKind::ResourceBusy.into(),
+        }
+    }
+
+    #[test_case(Dht22Error::ShortFrame(0), true; "no response")]
+    #[test_case(Dht22Error::ShortFrame(39), true; "short frame")]
+    #[test_case(Dht22Error::Checksum { expected: 1, actual: 2 }, true; "checksum")]
+    #[test_case(Dht22Error::Gpiod(reconfigure_failed()), false; "reconfigure")]
+    #[test_case(Dht22Error::Gpiod(GpiodError::WaitEdgeEvents { chip: "/dev/gpiochip0".into(), source: std::io::ErrorKind::Interrupted.into() }), false; "wait edge events")]
+    #[test]
+    fn test_is_transient(error: Dht22Error, expected: bool) {
+        assert_eq!(error.is_transient(), expected);
+    }
+
+    #[test]
+    fn test_decode_checksum_wraps() {
+        // 0xFF + 0xFF + 0x00 + 0x02 overflows a byte, and only the low byte is transmitted.
+        let frame = decode_frame(&pulses([0xFF, 0xFF, 0x00, 0x02, 0x00])).unwrap();
+        assert_eq!(frame, [0xFF, 0xFF, 0x00, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn test_decode_checksum_mismatch() {
+        let result = decode(&pulses([0x02, 0x8C, 0x01, 0x5F, 0xEF]), SensorModel::Dht22);
+        assert!(matches!(
+            result,
+            Err(Dht22Error::Checksum {
+                expected: 0xEF,
+                actual: 0xEE
+            })
+        ));
+    }
+
+    #[test_case(0; "no pulses")]
+    #[test_case(39; "one bit short")]
+    #[test]
+    fn test_decode_short_frame(len: usize) {
+        let mut pulses = pulses([0x02, 0x8C, 0x01, 0x5F, 0xEE]);
+        pulses.truncate(len);
+        assert!(
+            matches!(decode(&pulses, SensorModel::Dht22), Err(Dht22Error::ShortFrame(n)) if n == len)
+        );
+    }
+
+    #[test]
+    fn test_decode_ignores_trailing_pulses() {
+        let mut pulses = pulses([0x02, 0x8C, 0x01, 0x5F, 0xEE]);
+        pulses.push(ONE);
+        assert!(decode(&pulses, SensorModel::Dht22).is_ok());
+    }
+
+    // Builds the edges the sensor sends for `frame`: the 80us ack, then a 50us low before each bit,
+    // then the 50us low that ends the frame.
+    fn edges(frame: [u8; 5]) -> Vec<(EdgeKind, Duration)> {
+        let mut now = Duration::from_micros(30);
+        let mut edges = vec![];
+        let mut pulse = |low: Duration, high: Duration| {
+            edges.push((EdgeKind::Falling, now));
+            now += low;
+            edges.push((EdgeKind::Rising, now));
+            now += high;
+        };
+        pulse(Duration::from_micros(80), Duration::from_micros(80));
+        for p in pulses(frame) {
+            pulse(Duration::from_micros(50), p);
+        }
+        pulse(Duration::from_micros(50), Duration::ZERO);
+        edges
+    }
+
+    #[test]
+    fn test_high_pulses() {
+        let edges = [
+            (EdgeKind::Rising, Duration::from_micros(10)),
+            (EdgeKind::Falling, Duration::from_micros(37)),
+            (EdgeKind::Rising, Duration::from_micros(87)),
+            (EdgeKind::Falling, Duration::from_micros(157)),
+            (EdgeKind::Rising, Duration::from_micros(207)),
+        ];
+        assert_eq!(
+            high_pulses(&edges),
+            vec![Duration::from_micros(27), Duration::from_micros(70)]
+        );
+    }
+
+    #[test]
+    fn test_high_pulses_skips_missed_edges() {
+        // Two rising edges in a row means a falling edge was missed, so neither is measured.
+        let edges = [
+            (EdgeKind::Rising, Duration::from_micros(10)),
+            (EdgeKind::Rising, Duration::from_micros(37)),
+            (EdgeKind::Falling, Duration::from_micros(107)),
+        ];
+        assert_eq!(high_pulses(&edges), vec![Duration::from_micros(70)]);
+    }
+
+    #[test_case(0; "full response")]
+    #[test_case(2; "missed ack")]
+    #[test]
+    fn test_decode_edges(skip: usize) {
+        let edges = edges([0x02, 0x8C, 0x01, 0x5F, 0xEE]);
+        let reading = decode_edges(&edges[skip..], SensorModel::Dht22).unwrap();
+        assert_eq!(reading.humidity_percent, 65.2);
+        assert_eq!(reading.temperature_celsius, 35.1);
+    }
+
+    #[test]
+    fn test_decode_edges_no_response() {
+        assert!(matches!(
+            decode_edges(&[], SensorModel::Dht22),
+            Err(Dht22Error::ShortFrame(0))
+        ));
+    }
+
+    fn captured(edges: &[(EdgeKind, Duration)]) -> Vec<CapturedEdge> {
+        (0..)
+            .zip(edges)
+            .map(|(seqno, &(kind, timestamp))| CapturedEdge {
+                offset: 4,
+                kind,
+                timestamp,
+                seqno,
+            })
+            .collect()
+    }
+
+    // Hands out canned batches of edges, and records what the protocol did to the line.
+    #[derive(Default)]
+    struct ScriptedPin {
+        batches: Vec<Vec<CapturedEdge>>,
+        calls: Vec<&'static str>,
+        fail: bool,
+    }
+
+    impl SensorPin for ScriptedPin {
+        fn drive_low(&mut self) -> Result<(), GpiodError> {
+            self.calls.push("drive_low");
+            if self.fail {
+                return Err(reconfigure_failed());
+            }
+            Ok(())
+        }
+
+        fn release(&mut self) -> Result<(), GpiodError> {
+            self.calls.push("release");
+            Ok(())
+        }
+
+        fn wait_edges(&mut self, _: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+            self.calls.push("wait_edges");
+            if self.batches.is_empty() {
+                return Ok(vec![]);
+            }
+            Ok(self.batches.remove(0))
+        }
+
+        fn now(&self) -> Result<Duration, GpiodError> {
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn test_read() {
+        let edges = captured(&edges([0x02, 0x8C, 0x01, 0x5F, 0xEE]));
+        let mut pin = ScriptedPin {
+            batches: edges.chunks(32).map(<[_]>::to_vec).collect(),
+            ..Default::default()
+        };
+
+        let reading = read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap();
+        assert_eq!(reading.humidity_percent, 65.2);
+        assert_eq!(reading.temperature_celsius, 35.1);
+        // 84 edges arrive in 3 batches, then the line goes quiet.
+        assert_eq!(
+            pin.calls,
+            vec![
+                "drive_low",
+                "release",
+                "wait_edges",
+                "wait_edges",
+                "wait_edges",
+                "wait_edges"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_no_response() {
+        let mut pin = ScriptedPin::default();
+        assert!(matches!(
+            read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT),
+            Err(Dht22Error::ShortFrame(0))
+        ));
+    }
+
+    #[test]
+    fn test_read_pin_error() {
+        let mut pin = ScriptedPin {
+            fail: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT),
+            Err(Dht22Error::Gpiod(GpiodError::Reconfigure { .. }))
+        ));
+        assert_eq!(pin.calls, vec!["drive_low"]);
+    }
+
+    #[test]
+    fn test_read_stops_on_noisy_line() {
+        // A line that never settles would otherwise be read forever.
+        let noise = captured(&[(EdgeKind::Rising, Duration::ZERO); 100]);
+        let mut pin = ScriptedPin {
+            batches: vec![noise; 10],
+            ..Default::default()
+        };
+        assert!(read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT).is_err());
+        assert_eq!(pin.batches.len(), 7);
+    }
+}
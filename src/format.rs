@@ -0,0 +1,359 @@
+//! Formats readings are written out in, for people or for whatever ingests them.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::gpiod::Offset;
+use crate::model::SensorModel;
+use crate::sink::Sample;
+
+/// How each reading is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A sentence per reading, as `read` has always printed them.
+    #[default]
+    Human,
+    /// A JSON object per line.
+    Json,
+    /// Comma separated values, after a header naming the columns.
+    Csv,
+    /// InfluxDB line protocol.
+    Influx,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "influx" => Ok(Format::Influx),
+            _ => Err(format!(
+                "expected `human`, `json`, `csv` or `influx`, got {s:?}"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Human => write!(f, "human"),
+            Format::Json => write!(f, "json"),
+            Format::Csv => write!(f, "csv"),
+            Format::Influx => write!(f, "influx"),
+        }
+    }
+}
+
+/// Which sensor readings came from, for the formats that say so.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sensor {
+    /// Path of the chip the sensor is wired to, eg `/dev/gpiochip0`.
+    pub chip: String,
+    pub offset: Offset,
+    pub model: SensorModel,
+}
+
+/// A `key=value` tag added to every InfluxDB point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Tag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // InfluxDB drops tags with empty values, so they're refused rather than lost quietly.
+        match s.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !value.is_empty() => Ok(Tag {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            _ => Err(format!("expected `<key>=<value>`, got {s:?}")),
+        }
+    }
+}
+
+/// Where InfluxDB points go, and how they're tagged beyond the sensor's chip, offset and model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Influx {
+    pub measurement: String,
+    /// Tags for every point, which take the place of the sensor's own if they share a key.
+    pub tags: Vec<Tag>,
+}
+
+impl Default for Influx {
+    fn default() -> Self {
+        Influx {
+            measurement: "dht22".to_string(),
+            tags: vec![],
+        }
+    }
+}
+
+/// The columns of [`Format::Csv`], in order.
+pub const CSV_HEADER: &str = "timestamp,chip,offset,model,temperature,humidity,attempts";
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: u64,
+    chip: &'a str,
+    offset: Offset,
+    model: SensorModel,
+    temperature: f32,
+    humidity: f32,
+    attempts: u32,
+}
+
+/// Writes readings from `sensor` in `format`.
+///
+/// Timestamps are milliseconds since the Unix epoch, except in InfluxDB points, which default to
+/// nanoseconds. Temperatures are in °C and humidities in percent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formatter {
+    pub format: Format,
+    pub sensor: Sensor,
+    /// Only used for [`Format::Influx`].
+    pub influx: Influx,
+}
+
+impl Formatter {
+    /// Writes what has to come before the first reading, if anything.
+    pub fn write_header(&self, out: &mut impl Write) -> io::Result<()> {
+        match self.format {
+            Format::Csv => writeln!(out, "{CSV_HEADER}"),
+            Format::Human | Format::Json | Format::Influx => Ok(()),
+        }
+    }
+
+    /// Writes `sample` as a line.
+    pub fn write_sample(&self, out: &mut impl Write, sample: &Sample) -> io::Result<()> {
+        let Sample {
+            timestamp_ms,
+            reading,
+            attempts,
+        } = *sample;
+        let sensor = &self.sensor;
+        match self.format {
+            Format::Human => writeln!(out, "{sample}"),
+            Format::Json => {
+                let record = JsonRecord {
+                    timestamp: timestamp_ms,
+                    chip: &sensor.chip,
+                    offset: sensor.offset,
+                    model: sensor.model,
+                    temperature: reading.temperature_celsius,
+                    humidity: reading.humidity_percent,
+                    attempts,
+                };
+                serde_json::to_writer(&mut *out, &record)?;
+                writeln!(out)
+            }
+            Format::Csv => writeln!(
+                out,
+                "{timestamp_ms},{},{},{},{:.1},{:.1},{attempts}",
+                csv_field(&sensor.chip),
+                sensor.offset,
+                sensor.model,
+                reading.temperature_celsius,
+                reading.humidity_percent,
+            ),
+            Format::Influx => {
+                write!(out, "{}", escape(&self.influx.measurement, &[',', ' ']))?;
+                for (key, value) in self.tags() {
+                    write!(
+                        out,
+                        ",{}={}",
+                        escape(key, &[',', '=', ' ']),
+                        escape(&value, &[',', '=', ' '])
+                    )?;
+                }
+                writeln!(
+                    out,
+                    " temperature={:.1},humidity={:.1},attempts={attempts}i {}",
+                    reading.temperature_celsius,
+                    reading.humidity_percent,
+                    u128::from(timestamp_ms) * 1_000_000
+                )
+            }
+        }
+    }
+
+    // The sensor's tags, overridden or joined by the user's.
+    fn tags(&self) -> Vec<(&str, String)> {
+        let sensor = &self.sensor;
+        let mut tags = vec![
+            ("chip", sensor.chip.clone()),
+            ("offset", sensor.offset.to_string()),
+            ("model", sensor.model.to_string()),
+        ];
+        for tag in &self.influx.tags {
+            match tags.iter_mut().find(|(key, _)| *key == tag.key) {
+                Some((_, value)) => *value = tag.value.clone(),
+                None => tags.push((&tag.key, tag.value.clone())),
+            }
+        }
+        tags
+    }
+}
+
+// Quotes a CSV field if it would otherwise be split or mangled.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// Backslash-escapes the characters InfluxDB line protocol gives meaning to where `s` is used.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht22::Reading;
+    use simple_test_case::test_case;
+
+    const SAMPLE: Sample = Sample {
+        timestamp_ms: 1_700_000_000_123,
+        reading: Reading {
+            humidity_percent: 65.2,
+            temperature_celsius: -10.1,
+        },
+        attempts: 2,
+    };
+
+    fn formatter(format: Format) -> Formatter {
+        Formatter {
+            format,
+            sensor: Sensor {
+                chip: "/dev/gpiochip0".to_string(),
+                offset: 4,
+                model: SensorModel::Dht22,
+            },
+            influx: Influx::default(),
+        }
+    }
+
+    fn output(formatter: &Formatter) -> String {
+        let mut out = vec![];
+        formatter.write_header(&mut out).unwrap();
+        formatter.write_sample(&mut out, &SAMPLE).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_human() {
+        assert_eq!(
+            output(&formatter(Format::Human)),
+            "Humidity: 65.2%, Temperature: -10.1°C (2 attempts)\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let out = output(&formatter(Format::Json));
+        assert_eq!(out.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json["timestamp"], 1_700_000_000_123u64);
+        assert_eq!(json["chip"], "/dev/gpiochip0");
+        assert_eq!(json["offset"], 4);
+        assert_eq!(json["model"], "dht22");
+        assert_eq!(json["temperature"].as_f64().unwrap() as f32, -10.1);
+        assert_eq!(json["humidity"].as_f64().unwrap() as f32, 65.2);
+        assert_eq!(json["attempts"], 2);
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            output(&formatter(Format::Csv)),
+            "timestamp,chip,offset,model,temperature,humidity,attempts\n\
+             1700000000123,/dev/gpiochip0,4,dht22,-10.1,65.2,2\n"
+        );
+    }
+
+    #[test]
+    fn test_csv_quotes_chip() {
+        let mut formatter = formatter(Format::Csv);
+        formatter.sensor.chip = "/dev/odd,\"chip\"".to_string();
+        assert!(output(&formatter).ends_with(",\"/dev/odd,\"\"chip\"\"\",4,dht22,-10.1,65.2,2\n"));
+    }
+
+    #[test]
+    fn test_influx() {
+        assert_eq!(
+            output(&formatter(Format::Influx)),
+            "dht22,chip=/dev/gpiochip0,offset=4,model=dht22 \
+             temperature=-10.1,humidity=65.2,attempts=2i 1700000000123000000\n"
+        );
+    }
+
+    #[test]
+    fn test_influx_tags() {
+        let mut formatter = formatter(Format::Influx);
+        formatter.influx = Influx {
+            measurement: "climate readings".to_string(),
+            tags: vec![
+                "room=back bedroom".parse().unwrap(),
+                "chip=pi,4".parse().unwrap(),
+            ],
+        };
+        assert_eq!(
+            output(&formatter),
+            "climate\\ readings,chip=pi\\,4,offset=4,model=dht22,room=back\\ bedroom \
+             temperature=-10.1,humidity=65.2,attempts=2i 1700000000123000000\n"
+        );
+    }
+
+    #[test_case("human", Format::Human; "human")]
+    #[test_case("json", Format::Json; "json")]
+    #[test_case("csv", Format::Csv; "csv")]
+    #[test_case("influx", Format::Influx; "influx")]
+    #[test]
+    fn test_parse(s: &str, expected: Format) {
+        let format: Format = s.parse().unwrap();
+        assert_eq!(format, expected);
+        assert_eq!(format.to_string(), s);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("xml".parse::<Format>().is_err());
+    }
+
+    #[test_case("room=attic", "room", "attic"; "simple")]
+    #[test_case("url=a=b", "url", "a=b"; "equals in value")]
+    #[test]
+    fn test_parse_tag(s: &str, key: &str, value: &str) {
+        let tag: Tag = s.parse().unwrap();
+        assert_eq!(tag.key, key);
+        assert_eq!(tag.value, value);
+    }
+
+    #[test_case("room"; "no value")]
+    #[test_case("=attic"; "empty key")]
+    #[test_case("room="; "empty value")]
+    #[test]
+    fn test_parse_tag_invalid(s: &str) {
+        assert!(s.parse::<Tag>().is_err());
+    }
+}
@@ -1,751 +1,3482 @@
-#![allow(improper_ctypes)]
+//! Safe wrappers around libgpiod's chips, line settings and requests. Each wrapper owns the
+//! libgpiod object it points to and frees it when dropped.
+//!
+//! The enums describing a line's settings display as the names they serialize to, which are also
+//! the names the libgpiod tools print.
+
+// libgpiod's enum constants keep their C names, and are matched on below.
 #![allow(non_upper_case_globals)]
-#![allow(non_camel_case_types)]
-#![allow(non_snake_case)]
-#![allow(dead_code)]
 // I'm checking for null ptr derefs already
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
-include!("bindings/bindings.rs");
-
-use std::ptr;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::os::fd::RawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use serde::Serialize;
 use thiserror::Error;
 
-// The pin/line. Refered to as offsets in documentation as when you have multiple chips and want to
-// refer to a specific pin, you refer to it by its offset from its chip index.
-pub const OFFSET: std::os::raw::c_uint = 4;
+use crate::sys::*;
+
+/// The offset of a line (pin) on its chip.
+pub type Offset = std::os::raw::c_uint;
+
+/// Consumer name our requests show up under in `gpioinfo`.
+pub const CONSUMER: &str = "dht22_reader";
 
+/// Directory the kernel creates GPIO chip character devices in.
+pub const DEV_DIR: &str = "/dev";
+
+/// Something that went wrong talking to libgpiod.
+///
+/// Calls that fail in the kernel carry the `errno` they failed with as their source, so a line
+/// that's in use (`ResourceBusy`) or off limits (`PermissionDenied`) can be told apart from a
+/// bad request. See [`GpiodError::os_error`].
 #[derive(Error, Debug)]
 pub enum GpiodError {
-    #[error("Failed to open GPIO chip")]
-    OpenChip,
-    #[error("Failed to get chip info")]
-    GetChipInfo,
+    #[error("Failed to open GPIO chip {}: {source}", path.display())]
+    OpenChip { path: PathBuf, source: io::Error },
+    #[error("Failed to get chip info for {}: {source}", chip.display())]
+    GetChipInfo { chip: PathBuf, source: io::Error },
     #[error("Failed to get chip name")]
     GetChipName,
-    #[error("Failed to create GPIO settings object")]
-    CreateSettings,
-    #[error("Failed to set bias on settings object with bias {0}")]
-    SetBias(gpiod_line_bias),
-    #[error("Failed to set direction on settings object with direction {0}")]
-    SetDirection(gpiod_line_direction),
-    #[error("Failed to create GPIO config object")]
-    CreateConfig,
+    #[error("Failed to get chip label")]
+    GetChipLabel,
+    #[error("Failed to get chip path")]
+    GetChipPath,
+    #[error("No line named {0:?} on this chip")]
+    LineNotFound(String),
+    #[error("Failed to look up line {name:?} on {}: {source}", chip.display())]
+    FindLine {
+        chip: PathBuf,
+        name: String,
+        source: io::Error,
+    },
+    #[error("Failed to get line info for offset {offset} on {}: {source}", chip.display())]
+    GetLineInfo {
+        chip: PathBuf,
+        offset: Offset,
+        source: io::Error,
+    },
+    #[error("Failed to watch line info for offset {offset} on {}: {source}", chip.display())]
+    WatchLineInfo {
+        chip: PathBuf,
+        offset: Offset,
+        source: io::Error,
+    },
+    #[error(
+        "Failed to stop watching line info for offset {offset} on {}: {source}",
+        chip.display()
+    )]
+    UnwatchLineInfo {
+        chip: PathBuf,
+        offset: Offset,
+        source: io::Error,
+    },
+    #[error("Failed to wait for line info events on {}: {source}", chip.display())]
+    WaitInfoEvent { chip: PathBuf, source: io::Error },
+    #[error("Failed to read line info event on {}: {source}", chip.display())]
+    ReadInfoEvent { chip: PathBuf, source: io::Error },
+    #[error("Failed to create GPIO settings object: {0}")]
+    CreateSettings(#[source] io::Error),
+    #[error("Failed to set bias {bias:?} on settings object: {source}")]
+    SetBias { bias: Bias, source: io::Error },
+    #[error("Failed to set direction {direction:?} on settings object: {source}")]
+    SetDirection {
+        direction: Direction,
+        source: io::Error,
+    },
+    #[error("Failed to set drive {drive:?} on settings object: {source}")]
+    SetDrive { drive: Drive, source: io::Error },
+    #[error("Failed to set edge detection {edge:?} on settings object: {source}")]
+    SetEdgeDetection { edge: Edge, source: io::Error },
+    #[error("Failed to set event clock {clock:?} on settings object: {source}")]
+    SetEventClock {
+        clock: EventClock,
+        source: io::Error,
+    },
+    #[error("Failed to set output value {value:?} on settings object: {source}")]
+    SetOutputValue { value: Value, source: io::Error },
+    #[error("Unknown {0} value {1} returned by libgpiod")]
+    UnknownValue(&'static str, i64),
+    #[error("Failed to create GPIO config object: {0}")]
+    CreateConfig(#[source] io::Error),
+    #[error("Failed to create GPIO request config object: {0}")]
+    CreateRequestConfig(#[source] io::Error),
+    #[error("Consumer name {0:?} contains a NUL byte")]
+    InvalidConsumer(String),
+    #[error("Failed to add line settings to config object for offsets {offsets:?}: {source}")]
+    AddLineSettings {
+        offsets: Vec<Offset>,
+        source: io::Error,
+    },
+    #[error("Failed to set output values on config object: {0}")]
+    SetOutputValues(#[source] io::Error),
+    #[error("Failed to get line settings for offset {offset}: {source}")]
+    GetLineSettings { offset: Offset, source: io::Error },
     #[error("Encountered an unexpected null pointer")]
     NullPtr,
-    #[error("Failed to create line request")]
-    LineRequest,
-    #[error("Failed to set line request value")]
-    LineRequestSetValue,
-    #[error("Failed to get line request value")]
-    LineRequestGetValue,
-    #[error("Timeout waiting for line request value")]
-    Timeout,
-}
-
-// FIXME: this is the wrong abstraction, as ideally we want to make use of implementing drop on
-// structs in order for Rust to handle memory for us. IE, we should end up heading for:
-//
-// struct Chip {
-//     ptr: *mut gpiod_chip,
-// }
-// impl Drop for Chip {
-//     fn drop(&mut self) {
-//         if !self.ptr.is_null() {
-//             unsafe { gpiod_chip_close(self.ptr) }
-//         }
-//     }
-// }
-pub trait IGpiod {
-    fn chip(&self, ptr: *const u8) -> Result<*mut gpiod_chip, GpiodError>;
-
-    fn info(&self, chip: *mut gpiod_chip) -> Result<*mut gpiod_chip_info, GpiodError>;
-
-    fn name(&self, info: *mut gpiod_chip_info) -> Result<String, GpiodError>;
+    #[error("Failed to request offsets {offsets:?} on {}: {source}", chip.display())]
+    RequestLines {
+        chip: PathBuf,
+        offsets: Vec<Offset>,
+        source: io::Error,
+    },
+    #[error("Failed to get value of offset {offset} on {}: {source}", chip.display())]
+    GetValue {
+        chip: PathBuf,
+        offset: Offset,
+        source: io::Error,
+    },
+    #[error("Failed to get values of offsets {offsets:?} on {}: {source}", chip.display())]
+    GetValues {
+        chip: PathBuf,
+        offsets: Vec<Offset>,
+        source: io::Error,
+    },
+    #[error("Failed to set offset {offset} on {} to {value:?}: {source}", chip.display())]
+    SetValue {
+        chip: PathBuf,
+        offset: Offset,
+        value: Value,
+        source: io::Error,
+    },
+    #[error("Failed to set values of offsets {offsets:?} on {}: {source}", chip.display())]
+    SetValues {
+        chip: PathBuf,
+        offsets: Vec<Offset>,
+        source: io::Error,
+    },
+    #[error("Failed to reconfigure offsets {offsets:?} on {}: {source}", chip.display())]
+    Reconfigure {
+        chip: PathBuf,
+        offsets: Vec<Offset>,
+        source: io::Error,
+    },
+    #[error("Failed to create edge event buffer: {0}")]
+    CreateEdgeEventBuffer(#[source] io::Error),
+    #[error("Failed to wait for edge events on {}: {source}", chip.display())]
+    WaitEdgeEvents { chip: PathBuf, source: io::Error },
+    #[error("Failed to read edge events on {}: {source}", chip.display())]
+    ReadEdgeEvents { chip: PathBuf, source: io::Error },
+    #[error("Failed to copy edge event: {0}")]
+    CopyEdgeEvent(#[source] io::Error),
+    #[error("Expected {expected} line values but got {actual}")]
+    ValueCount { expected: usize, actual: usize },
+    #[error("Failed to read the clock edges are timestamped with: {0}")]
+    Clock(#[source] io::Error),
+}
 
-    fn settings(&self) -> Result<*mut gpiod_line_settings, GpiodError>;
+impl GpiodError {
+    /// Returns the `errno` the failing call left behind, if the failure came from the kernel.
+    /// Its [`kind`](io::Error::kind) tells eg a busy line (`ResourceBusy`) from one we aren't
+    /// allowed to use (`PermissionDenied`).
+    pub fn os_error(&self) -> Option<&io::Error> {
+        std::error::Error::source(self)?.downcast_ref()
+    }
+}
 
-    fn settings_set_bias(
-        &self,
-        settings: *mut gpiod_line_settings,
-        bias: gpiod_line_bias,
-    ) -> Result<(), GpiodError>;
+/// An open GPIO chip.
+///
+/// The underlying `gpiod_chip` is closed when this is dropped, so any lines requested from it
+/// should be released first.
+pub struct Chip {
+    ptr: *mut gpiod_chip,
+    // The path the chip was opened with, kept to say which chip an error came from.
+    path: PathBuf,
+}
 
-    fn settings_set_drive(
-        &self,
-        settings: *mut gpiod_line_settings,
-        bias: gpiod_line_drive,
-    ) -> Result<(), GpiodError>;
+impl Chip {
+    /// Returns the path the chip was opened with, eg `/dev/gpiochip0`.
+    pub fn path(&self) -> Result<String, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        let result = unsafe { gpiod_chip_get_path(self.ptr) };
+        if result.is_null() {
+            return Err(GpiodError::GetChipPath);
+        }
+        // Safety: We checked that result is not null
+        Ok(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
+    }
 
-    fn settings_set_direction(
-        &self,
-        settings: *mut gpiod_line_settings,
-        direction: gpiod_line_direction,
-    ) -> Result<(), GpiodError>;
+    /// Returns the file descriptor of the chip. The descriptor is owned by the chip and is
+    /// closed along with it.
+    pub fn fd(&self) -> RawFd {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        unsafe { gpiod_chip_get_fd(self.ptr) }
+    }
 
-    fn config(&self) -> Result<*mut gpiod_line_config, GpiodError>;
+    /// Looks up the offset of the line called `name`, eg `GPIO4`, on this chip.
+    pub fn line_offset_from_name(&self, name: &str) -> Result<Offset, GpiodError> {
+        let c_name = CString::new(name).map_err(|_| GpiodError::LineNotFound(name.to_string()))?;
+        // Safety: `ptr` is non-null for as long as the chip is alive, and `c_name` outlives the
+        // call.
+        let result = unsafe { gpiod_chip_get_line_offset_from_name(self.ptr, c_name.as_ptr()) };
+        Offset::try_from(result).map_err(|_| {
+            let source = io::Error::last_os_error();
+            // ENOENT means the chip has no such line, rather than that we couldn't look.
+            match source.raw_os_error() {
+                Some(libc::ENOENT) => GpiodError::LineNotFound(name.to_string()),
+                _ => GpiodError::FindLine {
+                    chip: self.path.clone(),
+                    name: name.to_string(),
+                    source,
+                },
+            }
+        })
+    }
 
-    fn config_add_settings(
-        &self,
-        config: *mut gpiod_line_config,
-        settings: *mut gpiod_line_settings,
-    ) -> Result<::std::os::raw::c_int, GpiodError>;
+    /// Retrieves a snapshot of the line at `offset`.
+    pub fn line_info(&self, offset: Offset) -> Result<LineInfo, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        let result = unsafe { gpiod_chip_get_line_info(self.ptr, offset) };
+        if result.is_null() {
+            return Err(GpiodError::GetLineInfo {
+                chip: self.path.clone(),
+                offset,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(LineInfo { ptr: result })
+    }
 
-    fn chip_request_lines(
-        &self,
-        chip: *mut gpiod_chip,
-        line_cfg: *mut gpiod_line_config,
-    ) -> Result<*mut gpiod_line_request, GpiodError>;
+    /// Starts watching the line at `offset` for changes made by any process, returning a
+    /// snapshot of the line as it is now. Changes are then read with [`Chip::read_info_event`].
+    pub fn watch_line_info(&self, offset: Offset) -> Result<LineInfo, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        let result = unsafe { gpiod_chip_watch_line_info(self.ptr, offset) };
+        if result.is_null() {
+            return Err(GpiodError::WatchLineInfo {
+                chip: self.path.clone(),
+                offset,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(LineInfo { ptr: result })
+    }
 
-    fn line_request_set_value(
-        &self,
-        request: *mut gpiod_line_request,
-        offset: ::std::os::raw::c_uint,
-        value: gpiod_line_value,
-    ) -> Result<(), GpiodError>;
+    /// Stops watching the line at `offset`.
+    pub fn unwatch_line_info(&self, offset: Offset) -> Result<(), GpiodError> {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        let result = unsafe { gpiod_chip_unwatch_line_info(self.ptr, offset) };
+        if result != 0 {
+            return Err(GpiodError::UnwatchLineInfo {
+                chip: self.path.clone(),
+                offset,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
 
-    fn line_request_reconfigure_lines(
-        &self,
-        request: *mut gpiod_line_request,
-        config: *mut gpiod_line_config,
-    ) -> Result<(), GpiodError>;
+    /// Waits up to `timeout` for a change to a watched line. Returns whether an event is ready
+    /// to be read.
+    pub fn wait_info_event(&self, timeout: Duration) -> Result<bool, GpiodError> {
+        let timeout = i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX);
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        match unsafe { gpiod_chip_wait_info_event(self.ptr, timeout) } {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(GpiodError::WaitInfoEvent {
+                chip: self.path.clone(),
+                source: io::Error::last_os_error(),
+            }),
+        }
+    }
 
-    fn line_request_get_value(
-        &self,
-        request: *mut gpiod_line_request,
-        offset: ::std::os::raw::c_uint,
-    ) -> Result<bool, GpiodError>;
-}
+    /// Reads the next change to a watched line, blocking until there is one.
+    pub fn read_info_event(&self) -> Result<InfoEvent, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        let event = unsafe { gpiod_chip_read_info_event(self.ptr) };
+        if event.is_null() {
+            return Err(GpiodError::ReadInfoEvent {
+                chip: self.path.clone(),
+                source: io::Error::last_os_error(),
+            });
+        }
+        // The line info belongs to the event, so it is copied out before the event is freed. A
+        // failed copy's errno is taken first too, as freeing is free to clobber it.
+        // Safety: We checked that event is not null, and it isn't used after being freed.
+        let (kind, timestamp, info) = unsafe {
+            let kind = gpiod_info_event_get_event_type(event);
+            let timestamp = gpiod_info_event_get_timestamp_ns(event);
+            let info = gpiod_line_info_copy(gpiod_info_event_get_line_info(event));
+            let copied = if info.is_null() {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(info)
+            };
+            gpiod_info_event_free(event);
+            (kind, timestamp, copied)
+        };
+        let info = info.map_err(|source| GpiodError::ReadInfoEvent {
+            chip: self.path.clone(),
+            source,
+        })?;
+        // Wrap the copy first so it is freed even if the event type is unknown.
+        let line_info = LineInfo { ptr: info };
 
-/// Concrete implementation of the GPIO device.
-pub struct Gpiod {}
+        Ok(InfoEvent {
+            kind: InfoEventKind::from_raw(kind)?,
+            timestamp: Duration::from_nanos(timestamp),
+            line_info,
+        })
+    }
 
-impl IGpiod for Gpiod {
-    /// Opens a GPIO chip.
-    ///
-    /// # Safety
-    /// - The returned `gpiod_chip` pointer must be freed properly.
-    fn chip(&self, ptr: *const u8) -> Result<*mut gpiod_chip, GpiodError> {
-        let result = unsafe { gpiod_chip_open(ptr) };
+    /// Retrieves a snapshot of the chip's information.
+    pub fn info(&self) -> Result<ChipInfo, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the chip is alive.
+        let result = unsafe { gpiod_chip_get_info(self.ptr) };
         if result.is_null() {
-            return Err(GpiodError::OpenChip);
+            return Err(GpiodError::GetChipInfo {
+                chip: self.path.clone(),
+                source: io::Error::last_os_error(),
+            });
         }
-        Ok(result)
+        Ok(ChipInfo { ptr: result })
     }
-    /// Retrieves chip information.
-    ///
-    /// # Safety
-    /// - `chip` must be a valid, non-null pointer to an open `gpiod_chip` instance.
-    /// - The returned `gpiod_chip_info` pointer must be freed properly.
-    fn info(&self, chip: *mut gpiod_chip) -> Result<*mut gpiod_chip_info, GpiodError> {
-        if chip.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_chip_get_info(chip) };
-        if result.is_null() {
-            return Err(GpiodError::GetChipInfo);
-        }
-        Ok(result)
+}
+
+impl Drop for Chip {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by gpiod_chip_open()
+        unsafe { gpiod_chip_close(self.ptr) }
     }
-    /// Retrieves the name of a GPIO chip.
-    ///
-    /// # Safety
-    /// - `info` must be a valid, non-null pointer to a `gpiod_chip_info` instance.
-    fn name(&self, info: *mut gpiod_chip_info) -> Result<String, GpiodError> {
-        if info.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_chip_info_get_name(info) };
+}
+
+/// Information about a GPIO chip. The underlying `gpiod_chip_info` is freed when this is
+/// dropped.
+pub struct ChipInfo {
+    ptr: *mut gpiod_chip_info,
+}
+
+impl ChipInfo {
+    /// Returns the name of the chip as represented in the kernel, eg `gpiochip0`.
+    pub fn name(&self) -> Result<String, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        let result = unsafe { gpiod_chip_info_get_name(self.ptr) };
         if result.is_null() {
             return Err(GpiodError::GetChipName);
         }
         // Safety: We checked that result is not null
-        Ok(unsafe {
-            std::ffi::CStr::from_ptr(result)
-                .to_string_lossy()
-                .to_string()
-        })
+        Ok(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
     }
-    /// Creates a new GPIO line settings object.
-    ///
-    /// # Safety
-    /// - The caller must ensure that the returned pointer is freed using `gpiod_line_settings_free()`.
-    fn settings(&self) -> Result<*mut gpiod_line_settings, GpiodError> {
-        let result = unsafe { gpiod_line_settings_new() };
+
+    /// Returns the label of the chip as represented in the kernel, eg `pinctrl-rp1`.
+    pub fn label(&self) -> Result<String, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        let result = unsafe { gpiod_chip_info_get_label(self.ptr) };
         if result.is_null() {
-            return Err(GpiodError::CreateSettings);
+            return Err(GpiodError::GetChipLabel);
         }
-        Ok(result)
+        // Safety: We checked that result is not null
+        Ok(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
     }
-    /// Sets the drive for a GPIO line.
-    ///
-    /// # Safety
-    /// - `settings` must be a valid, non-null pointer to a `gpiod_line_settings` instance.
-    fn settings_set_drive(
-        &self,
-        settings: *mut gpiod_line_settings,
-        drive: gpiod_line_drive,
-    ) -> Result<(), GpiodError> {
-        if settings.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_line_settings_set_drive(settings, drive) };
-        if result != 0 {
-            return Err(GpiodError::SetBias(drive as gpiod_line_bias));
-        }
-        Ok(())
+
+    /// Returns the number of lines exposed by the chip.
+    pub fn num_lines(&self) -> usize {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        unsafe { gpiod_chip_info_get_num_lines(self.ptr) }
     }
-    /// Sets the bias for a GPIO line.
-    ///
-    /// # Safety
-    /// - `settings` must be a valid, non-null pointer to a `gpiod_line_settings` instance.
-    fn settings_set_bias(
-        &self,
-        settings: *mut gpiod_line_settings,
-        bias: gpiod_line_bias,
-    ) -> Result<(), GpiodError> {
-        if settings.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_line_settings_set_bias(settings, bias) };
-        if result != 0 {
-            return Err(GpiodError::SetBias(bias));
-        }
-        Ok(())
+}
+
+impl Drop for ChipInfo {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_chip_get_info()
+        unsafe { gpiod_chip_info_free(self.ptr) }
     }
-    /// Sets the direction of a GPIO line.
-    ///
-    /// # Safety
-    /// - `settings` must be a valid, non-null pointer to a `gpiod_line_settings` instance.
-    fn settings_set_direction(
-        &self,
-        settings: *mut gpiod_line_settings,
-        direction: gpiod_line_direction,
-    ) -> Result<(), GpiodError> {
-        if settings.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_line_settings_set_direction(settings, direction) };
-        if result != 0 {
-            return Err(GpiodError::SetDirection(direction));
-        }
-        Ok(())
+}
+
+/// A snapshot of how a single line is currently set up, whoever has it requested. The
+/// underlying `gpiod_line_info` is freed when this is dropped.
+pub struct LineInfo {
+    ptr: *mut gpiod_line_info,
+}
+
+impl LineInfo {
+    pub fn offset(&self) -> Offset {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        unsafe { gpiod_line_info_get_offset(self.ptr) }
     }
-    /// Creates a new GPIO line configuration object.
-    ///
-    /// # Safety
-    /// - The caller must ensure that the returned pointer is freed using `gpiod_line_config_free()`.
-    fn config(&self) -> Result<*mut gpiod_line_config, GpiodError> {
-        let result = unsafe { gpiod_line_config_new() };
+
+    /// Returns the name of the line, eg `GPIO4`, if the chip names its lines.
+    pub fn name(&self) -> Option<String> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        let result = unsafe { gpiod_line_info_get_name(self.ptr) };
         if result.is_null() {
-            return Err(GpiodError::CreateConfig);
+            return None;
         }
-        Ok(result)
+        // Safety: We checked that result is not null
+        Some(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
     }
 
-    /// Adds a line setting to a configuration object.
-    ///
-    /// # Safety
-    /// - `config` must be a valid, non-null pointer to a `gpiod_line_config` instance.
-    /// - `settings` must be a valid, non-null pointer to a `gpiod_line_settings` instance.
-    fn config_add_settings(
-        &self,
-        config: *mut gpiod_line_config,
-        settings: *mut gpiod_line_settings,
-    ) -> Result<::std::os::raw::c_int, GpiodError> {
-        if config.is_null() || settings.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_line_config_add_line_settings(config, &OFFSET, 1, settings) };
-        if result != 0 {
-            return Err(GpiodError::CreateConfig);
-        }
-        Ok(result)
+    /// Returns whether the line is in use, either requested by a process or claimed by the
+    /// kernel. Used lines can't be requested.
+    pub fn is_used(&self) -> bool {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        unsafe { gpiod_line_info_is_used(self.ptr) }
     }
 
-    /// Requests a GPIO line.
-    ///
-    /// # Safety
-    /// - `chip` must be a valid, non-null pointer to a `gpiod_chip` instance.
-    /// - `line_cfg` must be a valid, non-null pointer to a `gpiod_line_config` instance.
-    /// - The returned `gpiod_line_request` pointer must be freed properly.
-    fn chip_request_lines(
-        &self,
-        chip: *mut gpiod_chip,
-        line_cfg: *mut gpiod_line_config,
-    ) -> Result<*mut gpiod_line_request, GpiodError> {
-        if chip.is_null() || line_cfg.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_chip_request_lines(chip, ptr::null_mut(), line_cfg) };
+    /// Returns the consumer the line was requested under, if it is in use and one was given.
+    pub fn consumer(&self) -> Option<String> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        let result = unsafe { gpiod_line_info_get_consumer(self.ptr) };
         if result.is_null() {
-            return Err(GpiodError::LineRequest);
+            return None;
         }
-        Ok(result)
+        // Safety: We checked that result is not null
+        Some(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
     }
 
-    /// Sets the value of a GPIO line request.
-    ///
-    /// # Safety
-    /// - `request` must be a valid, non-null pointer to a `gpiod_line_request` instance.
-    fn line_request_set_value(
-        &self,
-        request: *mut gpiod_line_request,
-        offset: ::std::os::raw::c_uint,
-        value: gpiod_line_value,
-    ) -> Result<(), GpiodError> {
-        if request.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_line_request_set_value(request, offset, value) };
-        if result != 0 {
-            return Err(GpiodError::LineRequestSetValue);
-        }
-        Ok(())
+    pub fn direction(&self) -> Result<Direction, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        Direction::from_raw(unsafe { gpiod_line_info_get_direction(self.ptr) })
     }
 
-    /// Reconfigures a line request.
-    ///
-    /// # Safety
-    /// - `request` must be a valid, non-null pointer to a `gpiod_line_request` instance.
-    /// - `config` must be a valid, non-null pointer to a `gpiod_line_config` instance.
-    fn line_request_reconfigure_lines(
-        &self,
-        request: *mut gpiod_line_request,
-        config: *mut gpiod_line_config,
-    ) -> Result<(), GpiodError> {
-        if request.is_null() || config.is_null() {
-            return Err(GpiodError::NullPtr);
-        }
-        let result = unsafe { gpiod_line_request_reconfigure_lines(request, config) };
-        if result != 0 {
-            return Err(GpiodError::LineRequestSetValue);
-        }
-        Ok(())
+    pub fn edge_detection(&self) -> Result<Edge, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        Edge::from_raw(unsafe { gpiod_line_info_get_edge_detection(self.ptr) })
     }
 
-    /// Gets the value of a GPIO line request.
-    ///
-    /// # Safety
-    /// - `request` must be a valid, non-null pointer to a `gpiod_line_request` instance.
-    fn line_request_get_value(
-        &self,
-        request: *mut gpiod_line_request,
-        offset: ::std::os::raw::c_uint,
-    ) -> Result<bool, GpiodError> {
-        if request.is_null() {
-            return Err(GpiodError::NullPtr);
+    pub fn bias(&self) -> Result<Bias, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        Bias::from_raw(unsafe { gpiod_line_info_get_bias(self.ptr) })
+    }
+
+    pub fn drive(&self) -> Result<Drive, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        Drive::from_raw(unsafe { gpiod_line_info_get_drive(self.ptr) })
+    }
+
+    pub fn is_active_low(&self) -> bool {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        unsafe { gpiod_line_info_is_active_low(self.ptr) }
+    }
+
+    /// Returns the debounce period, which is zero if the line isn't debounced.
+    pub fn debounce_period(&self) -> Duration {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        let period = unsafe { gpiod_line_info_get_debounce_period_us(self.ptr) };
+        // c_ulong is only 32 bits wide on 32-bit Raspberry Pi OS.
+        #[allow(clippy::useless_conversion)]
+        Duration::from_micros(period.into())
+    }
+
+    pub fn event_clock(&self) -> Result<EventClock, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the info is alive.
+        EventClock::from_raw(unsafe { gpiod_line_info_get_event_clock(self.ptr) })
+    }
+}
+
+impl Drop for LineInfo {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_chip_get_line_info()
+        unsafe { gpiod_line_info_free(self.ptr) }
+    }
+}
+
+/// Direction of a GPIO line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    /// Leave the direction unchanged when requesting or reconfiguring a line.
+    AsIs,
+    Input,
+    Output,
+}
+
+impl Direction {
+    fn raw(self) -> gpiod_line_direction {
+        match self {
+            Direction::AsIs => gpiod_line_direction_GPIOD_LINE_DIRECTION_AS_IS,
+            Direction::Input => gpiod_line_direction_GPIOD_LINE_DIRECTION_INPUT,
+            Direction::Output => gpiod_line_direction_GPIOD_LINE_DIRECTION_OUTPUT,
         }
-        let result = unsafe { gpiod_line_request_get_value(request, offset) };
-        if result == -1 {
-            return Err(GpiodError::LineRequestGetValue);
+    }
+
+    fn from_raw(raw: gpiod_line_direction) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_line_direction_GPIOD_LINE_DIRECTION_AS_IS => Ok(Direction::AsIs),
+            gpiod_line_direction_GPIOD_LINE_DIRECTION_INPUT => Ok(Direction::Input),
+            gpiod_line_direction_GPIOD_LINE_DIRECTION_OUTPUT => Ok(Direction::Output),
+            _ => Err(GpiodError::UnknownValue("direction", raw.into())),
         }
-        Ok(result == 1)
     }
 }
 
-// FIXME: Can this move into a Drop implementation?
-pub fn cleanup(
-    chip: Option<*mut gpiod_chip>,
-    info: Option<*mut gpiod_chip_info>,
-    settings: Option<*mut gpiod_line_settings>,
-    config: Option<*mut gpiod_line_config>,
-) {
-    if let Some(cf) = config {
-        // SAFETY: We explicitly checked config is not null when it was returned by
-        // gpiod_line_config_new()
-        unsafe { gpiod_line_config_free(cf) };
-    }
-    if let Some(s) = settings {
-        // SAFETY: We explicitly checked settigns is not null when it was returned by
-        // gpiod_line_settings_new()
-        unsafe { gpiod_line_settings_free(s) };
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Direction::AsIs => "as-is",
+            Direction::Input => "input",
+            Direction::Output => "output",
+        })
     }
-    if let Some(i) = info {
-        // SAFETY: We explicitly checked chip is not null when it was returned by gpiod_chip_open()
-        unsafe { gpiod_chip_info_free(i) };
+}
+
+/// Internal bias of a GPIO line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Bias {
+    /// Leave the bias unchanged when requesting or reconfiguring a line.
+    AsIs,
+    /// The bias state is unknown. Only ever reported, never accepted as a setting.
+    Unknown,
+    Disabled,
+    PullUp,
+    PullDown,
+}
+
+impl Bias {
+    fn raw(self) -> gpiod_line_bias {
+        match self {
+            Bias::AsIs => gpiod_line_bias_GPIOD_LINE_BIAS_AS_IS,
+            Bias::Unknown => gpiod_line_bias_GPIOD_LINE_BIAS_UNKNOWN,
+            Bias::Disabled => gpiod_line_bias_GPIOD_LINE_BIAS_DISABLED,
+            Bias::PullUp => gpiod_line_bias_GPIOD_LINE_BIAS_PULL_UP,
+            Bias::PullDown => gpiod_line_bias_GPIOD_LINE_BIAS_PULL_DOWN,
+        }
     }
-    if let Some(c) = chip {
-        // SAFETY: We explicitly checked info is not null when it was returned by gpiod_chip_get_info()
-        unsafe { gpiod_chip_close(c) };
+
+    fn from_raw(raw: gpiod_line_bias) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_line_bias_GPIOD_LINE_BIAS_AS_IS => Ok(Bias::AsIs),
+            gpiod_line_bias_GPIOD_LINE_BIAS_UNKNOWN => Ok(Bias::Unknown),
+            gpiod_line_bias_GPIOD_LINE_BIAS_DISABLED => Ok(Bias::Disabled),
+            gpiod_line_bias_GPIOD_LINE_BIAS_PULL_UP => Ok(Bias::PullUp),
+            gpiod_line_bias_GPIOD_LINE_BIAS_PULL_DOWN => Ok(Bias::PullDown),
+            _ => Err(GpiodError::UnknownValue("bias", raw.into())),
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #![allow(clippy::manual_c_str_literals)]
+impl fmt::Display for Bias {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Bias::AsIs => "as-is",
+            Bias::Unknown => "unknown",
+            Bias::Disabled => "disabled",
+            Bias::PullUp => "pull-up",
+            Bias::PullDown => "pull-down",
+        })
+    }
+}
 
-    use super::*;
-    use simple_test_case::test_case;
-    use std::ptr;
-    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+/// Drive mode of an output GPIO line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Drive {
+    PushPull,
+    /// Only drives the line low, leaving it floating (or to the bias) otherwise. This is what the
+    /// single-wire DHT22 bus needs, as the sensor also pulls the line low to respond.
+    OpenDrain,
+    OpenSource,
+}
 
-    static CONFIG_FREED: AtomicUsize = AtomicUsize::new(0);
-    static SETTINGS_FREED: AtomicUsize = AtomicUsize::new(0);
-    static INFO_FREED: AtomicUsize = AtomicUsize::new(0);
-    static CHIP_FREED: AtomicUsize = AtomicUsize::new(0);
+impl Drive {
+    fn raw(self) -> gpiod_line_drive {
+        match self {
+            Drive::PushPull => gpiod_line_drive_GPIOD_LINE_DRIVE_PUSH_PULL,
+            Drive::OpenDrain => gpiod_line_drive_GPIOD_LINE_DRIVE_OPEN_DRAIN,
+            Drive::OpenSource => gpiod_line_drive_GPIOD_LINE_DRIVE_OPEN_SOURCE,
+        }
+    }
 
-    // Override external functions provided by bindgen.
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_chip_open(ptr: *const i8) -> *mut gpiod_chip {
-        if ptr.is_null() {
-            return ptr::null_mut();
+    fn from_raw(raw: gpiod_line_drive) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_line_drive_GPIOD_LINE_DRIVE_PUSH_PULL => Ok(Drive::PushPull),
+            gpiod_line_drive_GPIOD_LINE_DRIVE_OPEN_DRAIN => Ok(Drive::OpenDrain),
+            gpiod_line_drive_GPIOD_LINE_DRIVE_OPEN_SOURCE => Ok(Drive::OpenSource),
+            _ => Err(GpiodError::UnknownValue("drive", raw.into())),
         }
-        1 as *mut gpiod_chip
     }
+}
 
-    // Mock result for gpiod_chip_get_info
-    static GPIOD_CHIP_GET_INFO_RESULT: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_chip_get_info(_: *mut gpiod_chip) -> *mut gpiod_chip_info {
-        if GPIOD_CHIP_GET_INFO_RESULT.load(Ordering::SeqCst) {
-            return 1 as *mut gpiod_chip_info;
+impl fmt::Display for Drive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Drive::PushPull => "push-pull",
+            Drive::OpenDrain => "open-drain",
+            Drive::OpenSource => "open-source",
+        })
+    }
+}
+
+/// Which edges of an input GPIO line generate events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Edge {
+    None,
+    Rising,
+    Falling,
+    Both,
+}
+
+impl Edge {
+    fn raw(self) -> gpiod_line_edge {
+        match self {
+            Edge::None => gpiod_line_edge_GPIOD_LINE_EDGE_NONE,
+            Edge::Rising => gpiod_line_edge_GPIOD_LINE_EDGE_RISING,
+            Edge::Falling => gpiod_line_edge_GPIOD_LINE_EDGE_FALLING,
+            Edge::Both => gpiod_line_edge_GPIOD_LINE_EDGE_BOTH,
         }
-        ptr::null_mut()
     }
 
-    // Mock result for gpiod_chip_info_get_name
-    static GPIOD_CHIP_GET_NAME_RESULT: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_chip_info_get_name(_: *mut gpiod_chip_info) -> *const i8 {
-        if GPIOD_CHIP_GET_NAME_RESULT.load(Ordering::SeqCst) {
-            return b"dummy_chip\0".as_ptr() as *const i8;
+    fn from_raw(raw: gpiod_line_edge) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_line_edge_GPIOD_LINE_EDGE_NONE => Ok(Edge::None),
+            gpiod_line_edge_GPIOD_LINE_EDGE_RISING => Ok(Edge::Rising),
+            gpiod_line_edge_GPIOD_LINE_EDGE_FALLING => Ok(Edge::Falling),
+            gpiod_line_edge_GPIOD_LINE_EDGE_BOTH => Ok(Edge::Both),
+            _ => Err(GpiodError::UnknownValue("edge", raw.into())),
         }
-        ptr::null()
     }
+}
 
-    // Mock result for gpiod_line_settings_new
-    static GPIOD_SETTINGS_CREATED: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_settings_new() -> *mut gpiod_line_settings {
-        if GPIOD_SETTINGS_CREATED.load(Ordering::SeqCst) {
-            return 1 as *mut gpiod_line_settings;
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Edge::None => "none",
+            Edge::Rising => "rising",
+            Edge::Falling => "falling",
+            Edge::Both => "both",
+        })
+    }
+}
+
+/// Clock used to timestamp edge events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventClock {
+    Monotonic,
+    Realtime,
+    /// Hardware timestamp engine, if the platform has one.
+    Hte,
+}
+
+impl EventClock {
+    fn raw(self) -> gpiod_line_clock {
+        match self {
+            EventClock::Monotonic => gpiod_line_clock_GPIOD_LINE_CLOCK_MONOTONIC,
+            EventClock::Realtime => gpiod_line_clock_GPIOD_LINE_CLOCK_REALTIME,
+            EventClock::Hte => gpiod_line_clock_GPIOD_LINE_CLOCK_HTE,
         }
-        ptr::null_mut()
     }
 
-    // Mock result for gpiod_line_settings_set_drive
-    static GPIOD_SETTINGS_BIAS_SET: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_settings_set_bias(
-        _: *mut gpiod_line_settings,
-        _: gpiod_line_bias,
-    ) -> i32 {
-        if GPIOD_SETTINGS_BIAS_SET.load(Ordering::SeqCst) {
-            return 0;
+    fn from_raw(raw: gpiod_line_clock) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_line_clock_GPIOD_LINE_CLOCK_MONOTONIC => Ok(EventClock::Monotonic),
+            gpiod_line_clock_GPIOD_LINE_CLOCK_REALTIME => Ok(EventClock::Realtime),
+            gpiod_line_clock_GPIOD_LINE_CLOCK_HTE => Ok(EventClock::Hte),
+            _ => Err(GpiodError::UnknownValue("event clock", raw.into())),
         }
-        -1
     }
+}
 
-    // Mock result for gpiod_line_settings_set_drive
-    static GPIOD_SETTINGS_DRIVE_SET: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_settings_set_drive(
-        _: *mut gpiod_line_settings,
-        _: gpiod_line_drive,
-    ) -> i32 {
-        if GPIOD_SETTINGS_DRIVE_SET.load(Ordering::SeqCst) {
-            return 0;
+impl fmt::Display for EventClock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            EventClock::Monotonic => "monotonic",
+            EventClock::Realtime => "realtime",
+            EventClock::Hte => "hte",
+        })
+    }
+}
+
+/// Logical value of a GPIO line. Whether active means high or low depends on the line's
+/// active-low setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Value {
+    Inactive,
+    Active,
+}
+
+impl Value {
+    fn raw(self) -> gpiod_line_value {
+        match self {
+            Value::Inactive => gpiod_line_value_GPIOD_LINE_VALUE_INACTIVE,
+            Value::Active => gpiod_line_value_GPIOD_LINE_VALUE_ACTIVE,
         }
-        -1
     }
 
-    // Mock result for gpiod_line_settings_set_direction
-    static GPIOD_SETTINGS_DIRECTION_SET: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_settings_set_direction(
-        _: *mut gpiod_line_settings,
-        _: gpiod_line_direction,
-    ) -> i32 {
-        if GPIOD_SETTINGS_DIRECTION_SET.load(Ordering::SeqCst) {
-            return 0;
+    fn from_raw(raw: gpiod_line_value) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_line_value_GPIOD_LINE_VALUE_INACTIVE => Ok(Value::Inactive),
+            gpiod_line_value_GPIOD_LINE_VALUE_ACTIVE => Ok(Value::Active),
+            _ => Err(GpiodError::UnknownValue("line value", raw.into())),
         }
-        -1
     }
+}
 
-    // Mock result for gpiod_line_config_new
-    static GPIOD_CONFIG_CREATED: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_config_new() -> *mut gpiod_line_config {
-        if GPIOD_CONFIG_CREATED.load(Ordering::SeqCst) {
-            return 1 as *mut gpiod_line_config;
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Value::Inactive => "inactive",
+            Value::Active => "active",
+        })
+    }
+}
+
+/// What happened to a watched line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InfoEventKind {
+    /// A process requested the line.
+    Requested,
+    /// The line was released.
+    Released,
+    /// The line was reconfigured by the process holding it.
+    ConfigChanged,
+}
+
+impl InfoEventKind {
+    fn from_raw(raw: gpiod_info_event_type) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_REQUESTED => Ok(InfoEventKind::Requested),
+            gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_RELEASED => Ok(InfoEventKind::Released),
+            gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_CONFIG_CHANGED => {
+                Ok(InfoEventKind::ConfigChanged)
+            }
+            _ => Err(GpiodError::UnknownValue("info event type", raw.into())),
         }
-        ptr::null_mut()
     }
+}
+
+impl fmt::Display for InfoEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            InfoEventKind::Requested => "requested",
+            InfoEventKind::Released => "released",
+            InfoEventKind::ConfigChanged => "config-changed",
+        })
+    }
+}
 
-    static GPIOD_CONFIG_ADD_SETTINGS_RESULT: AtomicBool = AtomicBool::new(false);
-    #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_config_add_line_settings(
-        _: *mut gpiod_line_config,
-        _: *const std::os::raw::c_uint,
-        _: i32,
-        _: *mut gpiod_line_settings,
-    ) -> i32 {
-        if GPIOD_CONFIG_ADD_SETTINGS_RESULT.load(Ordering::SeqCst) {
-            return 0;
+/// A change to a line being watched with [`Chip::watch_line_info`].
+pub struct InfoEvent {
+    pub kind: InfoEventKind,
+    /// When the change happened, read from the monotonic clock.
+    pub timestamp: Duration,
+    /// The line as it was right after the change.
+    pub line_info: LineInfo,
+}
+
+/// Which way a line changed in an edge event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// The line went from inactive to active.
+    Rising,
+    /// The line went from active to inactive.
+    Falling,
+}
+
+impl EdgeKind {
+    fn from_raw(raw: gpiod_edge_event_type) -> Result<Self, GpiodError> {
+        match raw {
+            gpiod_edge_event_type_GPIOD_EDGE_EVENT_RISING_EDGE => Ok(EdgeKind::Rising),
+            gpiod_edge_event_type_GPIOD_EDGE_EVENT_FALLING_EDGE => Ok(EdgeKind::Falling),
+            _ => Err(GpiodError::UnknownValue("edge event type", raw.into())),
         }
-        -1
+    }
+}
+
+/// Settings for one or more GPIO lines. The underlying `gpiod_line_settings` is freed when this
+/// is dropped.
+///
+/// Setters return `&mut Self` so they can be chained:
+///
+/// ```ignore
+/// let mut settings = LineSettings::new()?;
+/// settings
+///     .set_direction(Direction::Output)?
+///     .set_drive(Drive::OpenDrain)?
+///     .set_bias(Bias::Disabled)?;
+/// ```
+pub struct LineSettings {
+    ptr: *mut gpiod_line_settings,
+}
+
+impl LineSettings {
+    /// Creates a new settings object with every setting at its default.
+    pub fn new() -> Result<Self, GpiodError> {
+        let result = unsafe { gpiod_line_settings_new() };
+        if result.is_null() {
+            return Err(GpiodError::CreateSettings(io::Error::last_os_error()));
+        }
+        Ok(LineSettings { ptr: result })
+    }
+
+    /// Sets the direction of the line.
+    pub fn set_direction(&mut self, direction: Direction) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let result = unsafe { gpiod_line_settings_set_direction(self.ptr, direction.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetDirection {
+                direction,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn direction(&self) -> Result<Direction, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        Direction::from_raw(unsafe { gpiod_line_settings_get_direction(self.ptr) })
+    }
+
+    /// Sets which edges generate events. Only meaningful for input lines.
+    pub fn set_edge_detection(&mut self, edge: Edge) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let result = unsafe { gpiod_line_settings_set_edge_detection(self.ptr, edge.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetEdgeDetection {
+                edge,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn edge_detection(&self) -> Result<Edge, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        Edge::from_raw(unsafe { gpiod_line_settings_get_edge_detection(self.ptr) })
+    }
+
+    /// Sets the internal bias of the line.
+    pub fn set_bias(&mut self, bias: Bias) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let result = unsafe { gpiod_line_settings_set_bias(self.ptr, bias.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetBias {
+                bias,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn bias(&self) -> Result<Bias, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        Bias::from_raw(unsafe { gpiod_line_settings_get_bias(self.ptr) })
+    }
+
+    /// Sets the drive mode of the line. Only meaningful for output lines.
+    pub fn set_drive(&mut self, drive: Drive) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let result = unsafe { gpiod_line_settings_set_drive(self.ptr, drive.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetDrive {
+                drive,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn drive(&self) -> Result<Drive, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        Drive::from_raw(unsafe { gpiod_line_settings_get_drive(self.ptr) })
+    }
+
+    /// Sets whether the line's logical value is the inverse of its physical value.
+    pub fn set_active_low(&mut self, active_low: bool) -> &mut Self {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        unsafe { gpiod_line_settings_set_active_low(self.ptr, active_low) };
+        self
+    }
+
+    pub fn active_low(&self) -> bool {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        unsafe { gpiod_line_settings_get_active_low(self.ptr) }
+    }
+
+    /// Sets the debounce period of the line. The kernel works in whole microseconds, so anything
+    /// finer is truncated.
+    pub fn set_debounce_period(&mut self, period: Duration) -> &mut Self {
+        let period = period
+            .as_micros()
+            .try_into()
+            .unwrap_or(std::os::raw::c_ulong::MAX);
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        unsafe { gpiod_line_settings_set_debounce_period_us(self.ptr, period) };
+        self
+    }
+
+    pub fn debounce_period(&self) -> Duration {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let period = unsafe { gpiod_line_settings_get_debounce_period_us(self.ptr) };
+        // c_ulong is only 32 bits wide on 32-bit Raspberry Pi OS.
+        #[allow(clippy::useless_conversion)]
+        Duration::from_micros(period.into())
+    }
+
+    /// Sets the clock used to timestamp edge events.
+    pub fn set_event_clock(&mut self, clock: EventClock) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let result = unsafe { gpiod_line_settings_set_event_clock(self.ptr, clock.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetEventClock {
+                clock,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn event_clock(&self) -> Result<EventClock, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        EventClock::from_raw(unsafe { gpiod_line_settings_get_event_clock(self.ptr) })
+    }
+
+    /// Sets the value the line is driven to when it is requested as an output.
+    pub fn set_output_value(&mut self, value: Value) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        let result = unsafe { gpiod_line_settings_set_output_value(self.ptr, value.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetOutputValue {
+                value,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    pub fn output_value(&self) -> Result<Value, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the settings are alive.
+        Value::from_raw(unsafe { gpiod_line_settings_get_output_value(self.ptr) })
+    }
+}
+
+impl Drop for LineSettings {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_line_settings_new()
+        unsafe { gpiod_line_settings_free(self.ptr) }
+    }
+}
+
+/// Configuration for a set of lines, mapping each offset to its settings. The underlying
+/// `gpiod_line_config` is freed when this is dropped.
+pub struct LineConfig {
+    ptr: *mut gpiod_line_config,
+}
+
+impl LineConfig {
+    /// Creates a new, empty line config.
+    pub fn new() -> Result<Self, GpiodError> {
+        let result = unsafe { gpiod_line_config_new() };
+        if result.is_null() {
+            return Err(GpiodError::CreateConfig(io::Error::last_os_error()));
+        }
+        Ok(LineConfig { ptr: result })
+    }
+
+    /// Applies `settings` to every line in `offsets`. The settings are copied, so the same
+    /// `LineSettings` can be modified and added again for a different set of offsets.
+    pub fn add_line_settings(
+        &mut self,
+        offsets: &[Offset],
+        settings: &LineSettings,
+    ) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the config is alive, and `offsets` is valid
+        // for `offsets.len()` reads.
+        let result = unsafe {
+            gpiod_line_config_add_line_settings(
+                self.ptr,
+                offsets.as_ptr(),
+                offsets.len(),
+                settings.ptr,
+            )
+        };
+        if result != 0 {
+            return Err(GpiodError::AddLineSettings {
+                offsets: offsets.to_vec(),
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    /// Sets the output values of the configured lines, in the order they were added. This
+    /// overrides any output value in the lines' settings.
+    pub fn set_output_values(&mut self, values: &[Value]) -> Result<&mut Self, GpiodError> {
+        let values: Vec<gpiod_line_value> = values.iter().map(|v| v.raw()).collect();
+        // Safety: `ptr` is non-null for as long as the config is alive, and `values` is valid
+        // for `values.len()` reads.
+        let result =
+            unsafe { gpiod_line_config_set_output_values(self.ptr, values.as_ptr(), values.len()) };
+        if result != 0 {
+            return Err(GpiodError::SetOutputValues(io::Error::last_os_error()));
+        }
+        Ok(self)
+    }
+
+    /// Returns every offset that has settings in this config.
+    pub fn configured_offsets(&self) -> Vec<Offset> {
+        // Safety: `ptr` is non-null for as long as the config is alive.
+        let num = unsafe { gpiod_line_config_get_num_configured_offsets(self.ptr) };
+        let mut offsets = vec![0; num];
+        // Safety: `offsets` has room for `num` entries.
+        let written = unsafe {
+            gpiod_line_config_get_configured_offsets(self.ptr, offsets.as_mut_ptr(), num)
+        };
+        offsets.truncate(written);
+        offsets
+    }
+
+    /// Returns a copy of the settings configured for `offset`.
+    pub fn line_settings(&self, offset: Offset) -> Result<LineSettings, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the config is alive.
+        let result = unsafe { gpiod_line_config_get_line_settings(self.ptr, offset) };
+        if result.is_null() {
+            return Err(GpiodError::GetLineSettings {
+                offset,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(LineSettings { ptr: result })
+    }
+
+    /// Removes every offset and its settings from the config.
+    pub fn reset(&mut self) -> &mut Self {
+        // Safety: `ptr` is non-null for as long as the config is alive.
+        unsafe { gpiod_line_config_reset(self.ptr) };
+        self
+    }
+}
+
+impl Drop for LineConfig {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_line_config_new()
+        unsafe { gpiod_line_config_free(self.ptr) }
+    }
+}
+
+/// A set of lines requested from a chip. The lines are released back to the kernel when this is
+/// dropped.
+pub struct LineRequest {
+    ptr: *mut gpiod_line_request,
+    // The path of the chip the lines were requested from, kept to say which chip an error came
+    // from.
+    chip: PathBuf,
+}
+
+impl LineRequest {
+    /// Returns the name of the chip the lines were requested from, eg `gpiochip0`.
+    pub fn chip_name(&self) -> Result<String, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let result = unsafe { gpiod_line_request_get_chip_name(self.ptr) };
+        if result.is_null() {
+            return Err(GpiodError::GetChipName);
+        }
+        // Safety: We checked that result is not null
+        Ok(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
+    }
+
+    /// Returns the offsets of the requested lines, in the order `values` reports them.
+    pub fn requested_offsets(&self) -> Vec<Offset> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let num = unsafe { gpiod_line_request_get_num_requested_lines(self.ptr) };
+        let mut offsets = vec![0; num];
+        // Safety: `offsets` has room for `num` entries.
+        let written = unsafe {
+            gpiod_line_request_get_requested_offsets(self.ptr, offsets.as_mut_ptr(), num)
+        };
+        offsets.truncate(written);
+        offsets
+    }
+
+    /// Reads the value of a single requested line.
+    pub fn value(&self, offset: Offset) -> Result<Value, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let result = unsafe { gpiod_line_request_get_value(self.ptr, offset) };
+        if result == gpiod_line_value_GPIOD_LINE_VALUE_ERROR {
+            return Err(GpiodError::GetValue {
+                chip: self.chip.clone(),
+                offset,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Value::from_raw(result)
+    }
+
+    /// Sets the value of a single requested output line.
+    pub fn set_value(&mut self, offset: Offset, value: Value) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let result = unsafe { gpiod_line_request_set_value(self.ptr, offset, value.raw()) };
+        if result != 0 {
+            return Err(GpiodError::SetValue {
+                chip: self.chip.clone(),
+                offset,
+                value,
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    /// Reads the values of every requested line, in the order of `requested_offsets`.
+    pub fn values(&self) -> Result<Vec<Value>, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let num = unsafe { gpiod_line_request_get_num_requested_lines(self.ptr) };
+        let mut values = vec![gpiod_line_value_GPIOD_LINE_VALUE_INACTIVE; num];
+        // Safety: `values` has room for a value per requested line.
+        let result = unsafe { gpiod_line_request_get_values(self.ptr, values.as_mut_ptr()) };
+        if result != 0 {
+            let source = io::Error::last_os_error();
+            return Err(GpiodError::GetValues {
+                chip: self.chip.clone(),
+                offsets: self.requested_offsets(),
+                source,
+            });
+        }
+        values.into_iter().map(Value::from_raw).collect()
+    }
+
+    /// Reads the values of a subset of the requested lines, in the order of `offsets`.
+    pub fn values_subset(&self, offsets: &[Offset]) -> Result<Vec<Value>, GpiodError> {
+        let mut values = vec![gpiod_line_value_GPIOD_LINE_VALUE_INACTIVE; offsets.len()];
+        // Safety: `ptr` is non-null for as long as the request is alive, and `offsets` and
+        // `values` are both `offsets.len()` long.
+        let result = unsafe {
+            gpiod_line_request_get_values_subset(
+                self.ptr,
+                offsets.len(),
+                offsets.as_ptr(),
+                values.as_mut_ptr(),
+            )
+        };
+        if result != 0 {
+            return Err(GpiodError::GetValues {
+                chip: self.chip.clone(),
+                offsets: offsets.to_vec(),
+                source: io::Error::last_os_error(),
+            });
+        }
+        values.into_iter().map(Value::from_raw).collect()
+    }
+
+    /// Sets the values of every requested line, in the order of `requested_offsets`.
+    pub fn set_values(&mut self, values: &[Value]) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let num = unsafe { gpiod_line_request_get_num_requested_lines(self.ptr) };
+        // libgpiod reads one value per requested line, so a short slice would be read past.
+        if values.len() != num {
+            return Err(GpiodError::ValueCount {
+                expected: num,
+                actual: values.len(),
+            });
+        }
+        let values: Vec<gpiod_line_value> = values.iter().map(|v| v.raw()).collect();
+        // Safety: `values` holds a value per requested line.
+        let result = unsafe { gpiod_line_request_set_values(self.ptr, values.as_ptr()) };
+        if result != 0 {
+            let source = io::Error::last_os_error();
+            return Err(GpiodError::SetValues {
+                chip: self.chip.clone(),
+                offsets: self.requested_offsets(),
+                source,
+            });
+        }
+        Ok(self)
+    }
+
+    /// Sets the values of a subset of the requested lines. `offsets` and `values` are paired up
+    /// by index.
+    pub fn set_values_subset(
+        &mut self,
+        offsets: &[Offset],
+        values: &[Value],
+    ) -> Result<&mut Self, GpiodError> {
+        if values.len() != offsets.len() {
+            return Err(GpiodError::ValueCount {
+                expected: offsets.len(),
+                actual: values.len(),
+            });
+        }
+        let values: Vec<gpiod_line_value> = values.iter().map(|v| v.raw()).collect();
+        // Safety: `ptr` is non-null for as long as the request is alive, and `offsets` and
+        // `values` are the same length.
+        let result = unsafe {
+            gpiod_line_request_set_values_subset(
+                self.ptr,
+                offsets.len(),
+                offsets.as_ptr(),
+                values.as_ptr(),
+            )
+        };
+        if result != 0 {
+            return Err(GpiodError::SetValues {
+                chip: self.chip.clone(),
+                offsets: offsets.to_vec(),
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(self)
+    }
+
+    /// Applies a new configuration to the requested lines without releasing them, eg to turn
+    /// an output into an input.
+    pub fn reconfigure(&mut self, config: &LineConfig) -> Result<&mut Self, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        let result = unsafe { gpiod_line_request_reconfigure_lines(self.ptr, config.ptr) };
+        if result != 0 {
+            let source = io::Error::last_os_error();
+            return Err(GpiodError::Reconfigure {
+                chip: self.chip.clone(),
+                offsets: self.requested_offsets(),
+                source,
+            });
+        }
+        Ok(self)
+    }
+
+    /// Waits up to `timeout` for edge events on lines requested with edge detection. Returns
+    /// whether any events are ready to be read.
+    pub fn wait_edge_events(&self, timeout: Duration) -> Result<bool, GpiodError> {
+        let timeout = i64::try_from(timeout.as_nanos()).unwrap_or(i64::MAX);
+        // Safety: `ptr` is non-null for as long as the request is alive.
+        match unsafe { gpiod_line_request_wait_edge_events(self.ptr, timeout) } {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(GpiodError::WaitEdgeEvents {
+                chip: self.chip.clone(),
+                source: io::Error::last_os_error(),
+            }),
+        }
+    }
+
+    /// Reads as many pending edge events as fit into `buffer`, replacing its previous contents.
+    /// Blocks if no events are pending. Returns the number of events read.
+    pub fn read_edge_events(&mut self, buffer: &mut EdgeEventBuffer) -> Result<usize, GpiodError> {
+        // Safety: `ptr` and `buffer.ptr` are non-null for as long as each is alive, and at most
+        // the buffer's capacity is read.
+        let result =
+            unsafe { gpiod_line_request_read_edge_events(self.ptr, buffer.ptr, buffer.capacity()) };
+        usize::try_from(result).map_err(|_| GpiodError::ReadEdgeEvents {
+            chip: self.chip.clone(),
+            source: io::Error::last_os_error(),
+        })
+    }
+}
+
+impl Drop for LineRequest {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_chip_request_lines()
+        unsafe { gpiod_line_request_release(self.ptr) }
+    }
+}
+
+/// Storage for edge events read from a line request. The underlying `gpiod_edge_event_buffer` is
+/// freed when this is dropped.
+pub struct EdgeEventBuffer {
+    ptr: *mut gpiod_edge_event_buffer,
+}
+
+impl EdgeEventBuffer {
+    /// Creates a buffer with room for `capacity` events. libgpiod picks a default for zero, and
+    /// caps large capacities.
+    pub fn new(capacity: usize) -> Result<Self, GpiodError> {
+        let result = unsafe { gpiod_edge_event_buffer_new(capacity) };
+        if result.is_null() {
+            return Err(GpiodError::CreateEdgeEventBuffer(io::Error::last_os_error()));
+        }
+        Ok(EdgeEventBuffer { ptr: result })
+    }
+
+    /// Returns the maximum number of events the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        // Safety: `ptr` is non-null for as long as the buffer is alive.
+        unsafe { gpiod_edge_event_buffer_get_capacity(self.ptr) }
+    }
+
+    /// Returns the number of events filled in by the last read.
+    pub fn len(&self) -> usize {
+        // Safety: `ptr` is non-null for as long as the buffer is alive.
+        unsafe { gpiod_edge_event_buffer_get_num_events(self.ptr) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the events filled in by the last read.
+    pub fn iter(&self) -> EdgeEvents<'_> {
+        EdgeEvents {
+            buffer: self,
+            index: 0,
+        }
+    }
+
+    /// Returns the event at `index`, or `None` if the last read didn't fill that far.
+    pub fn event(&self, index: usize) -> Option<EdgeEvent<'_>> {
+        if index >= self.len() {
+            return None;
+        }
+        // Safety: `ptr` is non-null for as long as the buffer is alive, and we checked `index`
+        // is in bounds.
+        let result = unsafe { gpiod_edge_event_buffer_get_event(self.ptr, index as _) };
+        if result.is_null() {
+            return None;
+        }
+        Some(EdgeEvent {
+            ptr: result,
+            _buffer: PhantomData,
+        })
+    }
+}
+
+impl Drop for EdgeEventBuffer {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_edge_event_buffer_new()
+        unsafe { gpiod_edge_event_buffer_free(self.ptr) }
+    }
+}
+
+/// An edge event borrowed from an [`EdgeEventBuffer`]. It is only valid until the buffer is
+/// next read into, which the borrow enforces. Use [`EdgeEvent::copy`] to keep it for longer.
+pub struct EdgeEvent<'a> {
+    ptr: *mut gpiod_edge_event,
+    _buffer: PhantomData<&'a EdgeEventBuffer>,
+}
+
+impl EdgeEvent<'_> {
+    /// Returns whether the line rose or fell.
+    pub fn kind(&self) -> Result<EdgeKind, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the event is alive.
+        EdgeKind::from_raw(unsafe { gpiod_edge_event_get_event_type(self.ptr) })
+    }
+
+    /// Returns the time the kernel saw the edge, read from the line's event clock. Only the
+    /// difference between two timestamps is meaningful for the monotonic clock.
+    pub fn timestamp(&self) -> Duration {
+        // Safety: `ptr` is non-null for as long as the event is alive.
+        Duration::from_nanos(unsafe { gpiod_edge_event_get_timestamp_ns(self.ptr) })
+    }
+
+    /// Returns the offset of the line the edge was seen on.
+    pub fn line_offset(&self) -> Offset {
+        // Safety: `ptr` is non-null for as long as the event is alive.
+        unsafe { gpiod_edge_event_get_line_offset(self.ptr) }
+    }
+
+    /// Returns the sequence number of the event across every line in the request. A gap between
+    /// consecutive events means the kernel's buffer overflowed and events were dropped.
+    pub fn global_seqno(&self) -> u64 {
+        // Safety: `ptr` is non-null for as long as the event is alive.
+        let seqno = unsafe { gpiod_edge_event_get_global_seqno(self.ptr) };
+        // c_ulong is only 32 bits wide on 32-bit Raspberry Pi OS.
+        #[allow(clippy::useless_conversion)]
+        seqno.into()
+    }
+
+    /// Returns the sequence number of the event on its own line.
+    pub fn line_seqno(&self) -> u64 {
+        // Safety: `ptr` is non-null for as long as the event is alive.
+        let seqno = unsafe { gpiod_edge_event_get_line_seqno(self.ptr) };
+        // c_ulong is only 32 bits wide on 32-bit Raspberry Pi OS.
+        #[allow(clippy::useless_conversion)]
+        seqno.into()
+    }
+
+    /// Copies the event out of its buffer, so it can be kept after the buffer is read into again.
+    pub fn copy(&self) -> Result<OwnedEdgeEvent, GpiodError> {
+        // Safety: `ptr` is non-null for as long as the event is alive.
+        let result = unsafe { gpiod_edge_event_copy(self.ptr) };
+        if result.is_null() {
+            return Err(GpiodError::CopyEdgeEvent(io::Error::last_os_error()));
+        }
+        Ok(OwnedEdgeEvent {
+            event: EdgeEvent {
+                ptr: result,
+                _buffer: PhantomData,
+            },
+        })
+    }
+}
+
+/// An edge event copied out of its buffer with [`EdgeEvent::copy`]. The underlying
+/// `gpiod_edge_event` is freed when this is dropped.
+pub struct OwnedEdgeEvent {
+    event: EdgeEvent<'static>,
+}
+
+impl Deref for OwnedEdgeEvent {
+    type Target = EdgeEvent<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.event
+    }
+}
+
+impl Drop for OwnedEdgeEvent {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_edge_event_copy()
+        unsafe { gpiod_edge_event_free(self.event.ptr) }
+    }
+}
+
+/// Iterator over the events in an [`EdgeEventBuffer`], in the order the kernel reported them.
+pub struct EdgeEvents<'a> {
+    buffer: &'a EdgeEventBuffer,
+    index: usize,
+}
+
+impl<'a> Iterator for EdgeEvents<'a> {
+    type Item = EdgeEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.buffer.event(self.index)?;
+        self.index += 1;
+        Some(event)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.buffer.len().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> IntoIterator for &'a EdgeEventBuffer {
+    type Item = EdgeEvent<'a>;
+    type IntoIter = EdgeEvents<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Configuration for a line request as a whole, rather than for individual lines. The underlying
+/// `gpiod_request_config` is freed when this is dropped.
+pub struct RequestConfig {
+    ptr: *mut gpiod_request_config,
+}
+
+impl RequestConfig {
+    /// Creates a new request config with the consumer set to [`CONSUMER`].
+    pub fn new() -> Result<Self, GpiodError> {
+        let result = unsafe { gpiod_request_config_new() };
+        if result.is_null() {
+            return Err(GpiodError::CreateRequestConfig(io::Error::last_os_error()));
+        }
+        let mut config = RequestConfig { ptr: result };
+        config.set_consumer(CONSUMER)?;
+        Ok(config)
+    }
+
+    /// Creates a new request config with the consumer set to [`CONSUMER`] followed by `label`,
+    /// eg `dht22_reader:greenhouse`, so several sensors on one board can be told apart.
+    pub fn with_label(label: &str) -> Result<Self, GpiodError> {
+        let mut config = Self::new()?;
+        config.set_consumer(&format!("{CONSUMER}:{label}"))?;
+        Ok(config)
+    }
+
+    /// Sets the consumer name reported for the requested lines. The kernel truncates names
+    /// longer than 31 bytes.
+    pub fn set_consumer(&mut self, consumer: &str) -> Result<&mut Self, GpiodError> {
+        let consumer = CString::new(consumer)
+            .map_err(|_| GpiodError::InvalidConsumer(consumer.to_string()))?;
+        // Safety: `ptr` is non-null for as long as the config is alive. libgpiod copies the
+        // string, so it only needs to outlive the call.
+        unsafe { gpiod_request_config_set_consumer(self.ptr, consumer.as_ptr()) };
+        Ok(self)
+    }
+
+    /// Returns the consumer name, if one is set.
+    pub fn consumer(&self) -> Option<String> {
+        // Safety: `ptr` is non-null for as long as the config is alive.
+        let result = unsafe { gpiod_request_config_get_consumer(self.ptr) };
+        if result.is_null() {
+            return None;
+        }
+        // Safety: We checked that result is not null
+        Some(unsafe { CStr::from_ptr(result).to_string_lossy().to_string() })
+    }
+
+    /// Sets how many edge events the kernel buffers for the request before dropping them. Zero
+    /// leaves the choice to the kernel.
+    pub fn set_event_buffer_size(&mut self, size: usize) -> &mut Self {
+        // Safety: `ptr` is non-null for as long as the config is alive.
+        unsafe { gpiod_request_config_set_event_buffer_size(self.ptr, size) };
+        self
+    }
+
+    pub fn event_buffer_size(&self) -> usize {
+        // Safety: `ptr` is non-null for as long as the config is alive.
+        unsafe { gpiod_request_config_get_event_buffer_size(self.ptr) }
+    }
+}
+
+impl Drop for RequestConfig {
+    fn drop(&mut self) {
+        // SAFETY: We explicitly checked ptr is not null when it was returned by
+        // gpiod_request_config_new()
+        unsafe { gpiod_request_config_free(self.ptr) }
+    }
+}
+
+/// Returns whether `path` is a GPIO chip character device. Symlinks are followed.
+pub fn is_gpiochip_device(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // Safety: `path` outlives the call.
+    unsafe { gpiod_is_gpiochip_device(path.as_ptr()) }
+}
+
+/// Lists the GPIO chips in `dir` in numeric order, so `gpiochip2` comes before `gpiochip10`. A
+/// directory that can't be read has no chips.
+pub fn chip_paths(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|n| n.as_bytes().starts_with(b"gpiochip"))
+        })
+        .filter(|path| is_gpiochip_device(path))
+        .collect();
+    paths.sort_by(|a, b| {
+        let (a, b) = (a.as_os_str(), b.as_os_str());
+        a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+    });
+    paths
+}
+
+pub trait IGpiod {
+    fn chip(&self, path: &CStr) -> Result<Chip, GpiodError>;
+
+    fn chip_request_lines(
+        &self,
+        chip: &Chip,
+        req_cfg: &RequestConfig,
+        line_cfg: &LineConfig,
+    ) -> Result<LineRequest, GpiodError>;
+
+    /// Searches every chip in `dir` for a line called `name`, eg `GPIO4`, returning the path of
+    /// the first chip that has one along with the line's offset on it. Line names are stable
+    /// across board revisions where chip numbering and offsets are not.
+    fn find_line(&self, dir: &Path, name: &str) -> Result<(PathBuf, Offset), GpiodError> {
+        for path in chip_paths(dir) {
+            let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+                continue;
+            };
+            // A chip we can't open (eg for lack of permissions) can't be requested from either.
+            let Ok(chip) = self.chip(&c_path) else {
+                continue;
+            };
+            if let Ok(offset) = chip.line_offset_from_name(name) {
+                return Ok((path, offset));
+            }
+        }
+        Err(GpiodError::LineNotFound(name.to_string()))
+    }
+}
+
+/// Concrete implementation of the GPIO device.
+pub struct Gpiod {}
+
+impl IGpiod for Gpiod {
+    /// Opens a GPIO chip. The chip is closed when the returned `Chip` is dropped.
+    fn chip(&self, path: &CStr) -> Result<Chip, GpiodError> {
+        let result = unsafe { gpiod_chip_open(path.as_ptr()) };
+        let source = io::Error::last_os_error();
+        let path = PathBuf::from(std::ffi::OsStr::from_bytes(path.to_bytes()));
+        if result.is_null() {
+            return Err(GpiodError::OpenChip { path, source });
+        }
+        Ok(Chip { ptr: result, path })
+    }
+    /// Requests the lines configured in `line_cfg`. The lines are released when the returned
+    /// `LineRequest` is dropped.
+    fn chip_request_lines(
+        &self,
+        chip: &Chip,
+        req_cfg: &RequestConfig,
+        line_cfg: &LineConfig,
+    ) -> Result<LineRequest, GpiodError> {
+        let result = unsafe { gpiod_chip_request_lines(chip.ptr, req_cfg.ptr, line_cfg.ptr) };
+        if result.is_null() {
+            let source = io::Error::last_os_error();
+            return Err(GpiodError::RequestLines {
+                chip: chip.path.clone(),
+                offsets: line_cfg.configured_offsets(),
+                source,
+            });
+        }
+        Ok(LineRequest {
+            ptr: result,
+            chip: chip.path.clone(),
+        })
+    }
+}
+
+// The mocks below replace libgpiod's symbols for the whole unit test binary. The gpio-sim tests
+// in `tests/` are built separately, against the real library.
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::manual_c_str_literals)]
+    #![allow(clippy::manual_dangling_ptr)]
+
+    use super::*;
+    use simple_test_case::test_case;
+    use std::cell::{Cell, RefCell};
+    use std::os::raw::c_ulong;
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // Owned wrappers free their pointers on Drop, which happens in every test that creates one.
+    // Counting per thread keeps tests that run in parallel from seeing each other's frees.
+    thread_local! {
+        static INFO_FREED: Cell<usize> = const { Cell::new(0) };
+        static CHIP_FREED: Cell<usize> = const { Cell::new(0) };
+        static SETTINGS_FREED: Cell<usize> = const { Cell::new(0) };
+        static CONFIG_FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    // libgpiod reports why a call failed through errno, which the mocks set the same way.
+    fn set_errno(errno: i32) {
+        // Safety: errno is thread local, and always valid to write.
+        unsafe { *libc::__errno_location() = errno };
+    }
+
+    fn mock_chip() -> Chip {
+        Chip {
+            ptr: 1 as *mut gpiod_chip,
+            path: PathBuf::from("/dev/gpiochip0"),
+        }
+    }
+
+    // Override external functions provided by bindgen.
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_open(ptr: *const i8) -> *mut gpiod_chip {
+        if ptr.is_null() || *ptr == 0 {
+            set_errno(libc::ENOENT);
+            return ptr::null_mut();
+        }
+        MOCK_OPENED_CHIP.with(|c| *c.borrow_mut() = CStr::from_ptr(ptr).to_owned());
+        1 as *mut gpiod_chip
+    }
+
+    thread_local! {
+        // Path of the chip most recently opened, which line lookups are answered for.
+        static MOCK_OPENED_CHIP: RefCell<CString> = RefCell::new(CString::default());
+    }
+
+    // Only files named like chips count as chips.
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_is_gpiochip_device(path: *const i8) -> bool {
+        let path = Path::new(std::ffi::OsStr::from_bytes(CStr::from_ptr(path).to_bytes()));
+        path.file_name()
+            .is_some_and(|n| n.as_bytes().starts_with(b"gpiochip") && n != "gpiochip-fake")
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_get_path(_: *mut gpiod_chip) -> *const i8 {
+        b"/dev/gpiochip0\0".as_ptr() as *const i8
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_get_fd(_: *mut gpiod_chip) -> i32 {
+        3
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_get_line_offset_from_name(
+        _: *mut gpiod_chip,
+        name: *const i8,
+    ) -> i32 {
+        // Every chip has a GPIO4, but only gpiochip10 has a GPIO17.
+        let on_chip_10 = MOCK_OPENED_CHIP.with(|c| c.borrow().to_bytes().ends_with(b"gpiochip10"));
+        match CStr::from_ptr(name).to_bytes() {
+            b"GPIO4" => 4,
+            b"GPIO17" if on_chip_10 => 17,
+            // Looking a line up on gpiochip-broken fails outright.
+            _ if MOCK_OPENED_CHIP.with(|c| c.borrow().to_bytes().ends_with(b"gpiochip-broken")) => {
+                set_errno(libc::EIO);
+                -1
+            }
+            _ => {
+                set_errno(libc::ENOENT);
+                -1
+            }
+        }
+    }
+
+    // Mock result for gpiod_chip_get_info, which always fails for gpiochip-noinfo.
+    static GPIOD_CHIP_GET_INFO_RESULT: AtomicBool = AtomicBool::new(false);
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_get_info(_: *mut gpiod_chip) -> *mut gpiod_chip_info {
+        if MOCK_OPENED_CHIP.with(|c| c.borrow().to_bytes().ends_with(b"gpiochip-noinfo")) {
+            set_errno(libc::EIO);
+            return ptr::null_mut();
+        }
+        if GPIOD_CHIP_GET_INFO_RESULT.load(Ordering::SeqCst) {
+            return 1 as *mut gpiod_chip_info;
+        }
+        ptr::null_mut()
+    }
+
+    // Mock result for gpiod_chip_info_get_name
+    static GPIOD_CHIP_GET_NAME_RESULT: AtomicBool = AtomicBool::new(false);
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_info_get_name(_: *mut gpiod_chip_info) -> *const i8 {
+        if GPIOD_CHIP_GET_NAME_RESULT.load(Ordering::SeqCst) {
+            return b"dummy_chip\0".as_ptr() as *const i8;
+        }
+        ptr::null()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_info_get_label(_: *mut gpiod_chip_info) -> *const i8 {
+        b"pinctrl-rp1\0".as_ptr() as *const i8
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_info_get_num_lines(_: *mut gpiod_chip_info) -> usize {
+        54
+    }
+
+    thread_local! {
+        static LINE_INFO_FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    // Line info is handed out as its offset plus one. Offset 4 is our sensor, requested as an
+    // edge-detecting input; every other line is an unnamed, unused output.
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_get_line_info(
+        _: *mut gpiod_chip,
+        offset: Offset,
+    ) -> *mut gpiod_line_info {
+        if offset >= 54 {
+            set_errno(libc::EINVAL);
+            return ptr::null_mut();
+        }
+        (offset as usize + 1) as *mut gpiod_line_info
+    }
+
+    fn mock_line_info_offset(info: *mut gpiod_line_info) -> Offset {
+        (info as usize - 1) as Offset
+    }
+
+    fn is_sensor(info: *mut gpiod_line_info) -> bool {
+        mock_line_info_offset(info) == 4
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_offset(info: *mut gpiod_line_info) -> Offset {
+        mock_line_info_offset(info)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_name(info: *mut gpiod_line_info) -> *const i8 {
+        if is_sensor(info) {
+            return b"GPIO4\0".as_ptr() as *const i8;
+        }
+        ptr::null()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_is_used(info: *mut gpiod_line_info) -> bool {
+        is_sensor(info)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_consumer(info: *mut gpiod_line_info) -> *const i8 {
+        if is_sensor(info) {
+            return b"dht22_reader\0".as_ptr() as *const i8;
+        }
+        ptr::null()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_direction(
+        info: *mut gpiod_line_info,
+    ) -> gpiod_line_direction {
+        if is_sensor(info) {
+            return gpiod_line_direction_GPIOD_LINE_DIRECTION_INPUT;
+        }
+        gpiod_line_direction_GPIOD_LINE_DIRECTION_OUTPUT
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_edge_detection(
+        info: *mut gpiod_line_info,
+    ) -> gpiod_line_edge {
+        if is_sensor(info) {
+            return gpiod_line_edge_GPIOD_LINE_EDGE_BOTH;
+        }
+        gpiod_line_edge_GPIOD_LINE_EDGE_NONE
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_bias(_: *mut gpiod_line_info) -> gpiod_line_bias {
+        gpiod_line_bias_GPIOD_LINE_BIAS_DISABLED
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_drive(
+        _: *mut gpiod_line_info,
+    ) -> gpiod_line_drive {
+        gpiod_line_drive_GPIOD_LINE_DRIVE_OPEN_DRAIN
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_is_active_low(_: *mut gpiod_line_info) -> bool {
+        false
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_debounce_period_us(
+        info: *mut gpiod_line_info,
+    ) -> c_ulong {
+        if is_sensor(info) {
+            return 0;
+        }
+        250
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_get_event_clock(
+        _: *mut gpiod_line_info,
+    ) -> gpiod_line_clock {
+        gpiod_line_clock_GPIOD_LINE_CLOCK_MONOTONIC
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_free(_ptr: *mut gpiod_line_info) {
+        LINE_INFO_FREED.with(|c| c.set(c.get() + 1));
+    }
+
+    // Copies are indistinguishable from the original, which is all the mocked getters need.
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_info_copy(
+        info: *mut gpiod_line_info,
+    ) -> *mut gpiod_line_info {
+        // Line info pointers are offset + 1, and copies of lines from 54 on fail.
+        if info as usize > 54 {
+            set_errno(libc::ENOMEM);
+            return ptr::null_mut();
+        }
+        info
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_watch_line_info(
+        chip: *mut gpiod_chip,
+        offset: Offset,
+    ) -> *mut gpiod_line_info {
+        gpiod_chip_get_line_info(chip, offset)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_unwatch_line_info(
+        _: *mut gpiod_chip,
+        offset: Offset,
+    ) -> i32 {
+        if offset >= 54 {
+            return -1;
+        }
+        0
+    }
+
+    thread_local! {
+        // Changes the mocked kernel has queued for watched lines, as (type, timestamp, offset).
+        static MOCK_INFO_EVENTS: RefCell<Vec<(gpiod_info_event_type, u64, Offset)>> =
+            const { RefCell::new(Vec::new()) };
+        // The event most recently read, which the gpiod_info_event_* getters answer for.
+        static MOCK_INFO_EVENT: Cell<(gpiod_info_event_type, u64, Offset)> =
+            const { Cell::new((0, 0, 0)) };
+        static INFO_EVENT_FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_wait_info_event(_: *mut gpiod_chip, _: i64) -> i32 {
+        MOCK_INFO_EVENTS.with(|e| i32::from(!e.borrow().is_empty()))
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_chip_read_info_event(
+        _: *mut gpiod_chip,
+    ) -> *mut gpiod_info_event {
+        let event = MOCK_INFO_EVENTS.with(|e| {
+            let mut events = e.borrow_mut();
+            (!events.is_empty()).then(|| events.remove(0))
+        });
+        match event {
+            Some(event) => {
+                MOCK_INFO_EVENT.with(|e| e.set(event));
+                1 as *mut gpiod_info_event
+            }
+            None => ptr::null_mut(),
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_info_event_get_event_type(
+        _: *mut gpiod_info_event,
+    ) -> gpiod_info_event_type {
+        MOCK_INFO_EVENT.with(Cell::get).0
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_info_event_get_timestamp_ns(_: *mut gpiod_info_event) -> u64 {
+        MOCK_INFO_EVENT.with(Cell::get).1
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_info_event_get_line_info(
+        _: *mut gpiod_info_event,
+    ) -> *mut gpiod_line_info {
+        (MOCK_INFO_EVENT.with(Cell::get).2 as usize + 1) as *mut gpiod_line_info
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_info_event_free(_ptr: *mut gpiod_info_event) {
+        INFO_EVENT_FREED.with(|c| c.set(c.get() + 1));
+        // Freeing may clobber errno, so the mock always does.
+        set_errno(libc::EBADF);
+    }
+
+    // Mock result for gpiod_line_settings_new
+    static GPIOD_SETTINGS_CREATED: AtomicBool = AtomicBool::new(false);
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_new() -> *mut gpiod_line_settings {
+        if GPIOD_SETTINGS_CREATED.load(Ordering::SeqCst) {
+            return 1 as *mut gpiod_line_settings;
+        }
+        ptr::null_mut()
+    }
+
+    // Backing store for the gpiod_line_settings_* mocks, so getters return whatever the matching
+    // setter was last called with on the current thread.
+    struct MockSettings {
+        direction: gpiod_line_direction,
+        edge: gpiod_line_edge,
+        bias: gpiod_line_bias,
+        drive: gpiod_line_drive,
+        active_low: bool,
+        debounce_period_us: c_ulong,
+        event_clock: gpiod_line_clock,
+        output_value: gpiod_line_value,
+    }
+
+    thread_local! {
+        static MOCK_SETTINGS: RefCell<MockSettings> = const {
+            RefCell::new(MockSettings {
+                direction: gpiod_line_direction_GPIOD_LINE_DIRECTION_AS_IS,
+                edge: gpiod_line_edge_GPIOD_LINE_EDGE_NONE,
+                bias: gpiod_line_bias_GPIOD_LINE_BIAS_AS_IS,
+                drive: gpiod_line_drive_GPIOD_LINE_DRIVE_PUSH_PULL,
+                active_low: false,
+                debounce_period_us: 0,
+                event_clock: gpiod_line_clock_GPIOD_LINE_CLOCK_MONOTONIC,
+                output_value: gpiod_line_value_GPIOD_LINE_VALUE_INACTIVE,
+            })
+        };
+        // Mock result for the fallible gpiod_line_settings_set_* functions
+        static GPIOD_SETTINGS_SET_RESULT: Cell<bool> = const { Cell::new(true) };
+    }
+
+    // Applies `store` and reports success, unless the fallible setters are set to fail.
+    fn mock_settings_set(store: impl FnOnce(&mut MockSettings)) -> i32 {
+        if GPIOD_SETTINGS_SET_RESULT.with(Cell::get) {
+            MOCK_SETTINGS.with(|s| store(&mut s.borrow_mut()));
+            return 0;
+        }
+        -1
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_direction(
+        _: *mut gpiod_line_settings,
+        direction: gpiod_line_direction,
+    ) -> i32 {
+        mock_settings_set(|s| s.direction = direction)
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_direction(
+        _: *mut gpiod_line_settings,
+    ) -> gpiod_line_direction {
+        MOCK_SETTINGS.with(|s| s.borrow().direction)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_edge_detection(
+        _: *mut gpiod_line_settings,
+        edge: gpiod_line_edge,
+    ) -> i32 {
+        mock_settings_set(|s| s.edge = edge)
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_edge_detection(
+        _: *mut gpiod_line_settings,
+    ) -> gpiod_line_edge {
+        MOCK_SETTINGS.with(|s| s.borrow().edge)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_bias(
+        _: *mut gpiod_line_settings,
+        bias: gpiod_line_bias,
+    ) -> i32 {
+        mock_settings_set(|s| s.bias = bias)
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_bias(
+        _: *mut gpiod_line_settings,
+    ) -> gpiod_line_bias {
+        MOCK_SETTINGS.with(|s| s.borrow().bias)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_drive(
+        _: *mut gpiod_line_settings,
+        drive: gpiod_line_drive,
+    ) -> i32 {
+        mock_settings_set(|s| s.drive = drive)
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_drive(
+        _: *mut gpiod_line_settings,
+    ) -> gpiod_line_drive {
+        MOCK_SETTINGS.with(|s| s.borrow().drive)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_active_low(
+        _: *mut gpiod_line_settings,
+        active_low: bool,
+    ) {
+        MOCK_SETTINGS.with(|s| s.borrow_mut().active_low = active_low);
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_active_low(
+        _: *mut gpiod_line_settings,
+    ) -> bool {
+        MOCK_SETTINGS.with(|s| s.borrow().active_low)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_debounce_period_us(
+        _: *mut gpiod_line_settings,
+        period: c_ulong,
+    ) {
+        MOCK_SETTINGS.with(|s| s.borrow_mut().debounce_period_us = period);
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_debounce_period_us(
+        _: *mut gpiod_line_settings,
+    ) -> c_ulong {
+        MOCK_SETTINGS.with(|s| s.borrow().debounce_period_us)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_event_clock(
+        _: *mut gpiod_line_settings,
+        event_clock: gpiod_line_clock,
+    ) -> i32 {
+        mock_settings_set(|s| s.event_clock = event_clock)
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_event_clock(
+        _: *mut gpiod_line_settings,
+    ) -> gpiod_line_clock {
+        MOCK_SETTINGS.with(|s| s.borrow().event_clock)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_set_output_value(
+        _: *mut gpiod_line_settings,
+        value: gpiod_line_value,
+    ) -> i32 {
+        mock_settings_set(|s| s.output_value = value)
+    }
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_settings_get_output_value(
+        _: *mut gpiod_line_settings,
+    ) -> gpiod_line_value {
+        MOCK_SETTINGS.with(|s| s.borrow().output_value)
+    }
+
+    // Mock result for gpiod_line_config_new
+    static GPIOD_CONFIG_CREATED: AtomicBool = AtomicBool::new(false);
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_new() -> *mut gpiod_line_config {
+        if GPIOD_CONFIG_CREATED.load(Ordering::SeqCst) {
+            return 1 as *mut gpiod_line_config;
+        }
+        ptr::null_mut()
+    }
+
+    thread_local! {
+        // Mock result for the fallible gpiod_line_config_* functions
+        static GPIOD_CONFIG_SET_RESULT: Cell<bool> = const { Cell::new(true) };
+        // Offsets added to the mocked config on the current thread, in order.
+        static MOCK_CONFIG_OFFSETS: RefCell<Vec<Offset>> = const { RefCell::new(Vec::new()) };
+        static MOCK_CONFIG_OUTPUT_VALUES: RefCell<Vec<gpiod_line_value>> =
+            const { RefCell::new(Vec::new()) };
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_add_line_settings(
+        _: *mut gpiod_line_config,
+        offsets: *const Offset,
+        num_offsets: usize,
+        _: *mut gpiod_line_settings,
+    ) -> i32 {
+        if !GPIOD_CONFIG_SET_RESULT.with(Cell::get) {
+            return -1;
+        }
+        let offsets = std::slice::from_raw_parts(offsets, num_offsets);
+        MOCK_CONFIG_OFFSETS.with(|o| o.borrow_mut().extend_from_slice(offsets));
+        0
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_set_output_values(
+        _: *mut gpiod_line_config,
+        values: *const gpiod_line_value,
+        num_values: usize,
+    ) -> i32 {
+        if !GPIOD_CONFIG_SET_RESULT.with(Cell::get) {
+            return -1;
+        }
+        let values = std::slice::from_raw_parts(values, num_values);
+        MOCK_CONFIG_OUTPUT_VALUES.with(|v| *v.borrow_mut() = values.to_vec());
+        0
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_get_num_configured_offsets(
+        _: *mut gpiod_line_config,
+    ) -> usize {
+        MOCK_CONFIG_OFFSETS.with(|o| o.borrow().len())
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_get_configured_offsets(
+        _: *mut gpiod_line_config,
+        offsets: *mut Offset,
+        max_offsets: usize,
+    ) -> usize {
+        MOCK_CONFIG_OFFSETS.with(|o| {
+            let o = o.borrow();
+            let num = o.len().min(max_offsets);
+            ptr::copy_nonoverlapping(o.as_ptr(), offsets, num);
+            num
+        })
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_get_line_settings(
+        _: *mut gpiod_line_config,
+        offset: Offset,
+    ) -> *mut gpiod_line_settings {
+        if MOCK_CONFIG_OFFSETS.with(|o| o.borrow().contains(&offset)) {
+            return 1 as *mut gpiod_line_settings;
+        }
+        ptr::null_mut()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_config_reset(_: *mut gpiod_line_config) {
+        MOCK_CONFIG_OFFSETS.with(|o| o.borrow_mut().clear());
+    }
+
+    thread_local! {
+        // Mock result for gpiod_request_config_new
+        static GPIOD_REQUEST_CONFIG_CREATED: Cell<bool> = const { Cell::new(true) };
+        static MOCK_CONSUMER: RefCell<Option<CString>> = const { RefCell::new(None) };
+        static MOCK_EVENT_BUFFER_SIZE: Cell<usize> = const { Cell::new(0) };
+        static REQUEST_CONFIG_FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_request_config_new() -> *mut gpiod_request_config {
+        if GPIOD_REQUEST_CONFIG_CREATED.with(Cell::get) {
+            MOCK_CONSUMER.with(|c| *c.borrow_mut() = None);
+            return 1 as *mut gpiod_request_config;
+        }
+        ptr::null_mut()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_request_config_free(_: *mut gpiod_request_config) {
+        REQUEST_CONFIG_FREED.with(|c| c.set(c.get() + 1));
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_request_config_set_consumer(
+        _: *mut gpiod_request_config,
+        consumer: *const i8,
+    ) {
+        let consumer = CStr::from_ptr(consumer).to_owned();
+        MOCK_CONSUMER.with(|c| *c.borrow_mut() = Some(consumer));
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_request_config_get_consumer(
+        _: *mut gpiod_request_config,
+    ) -> *const i8 {
+        // The mocked string lives in a thread local, so the pointer stays valid until the next
+        // set_consumer call on this thread.
+        MOCK_CONSUMER.with(|c| c.borrow().as_ref().map_or(ptr::null(), |c| c.as_ptr()))
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_request_config_set_event_buffer_size(
+        _: *mut gpiod_request_config,
+        size: usize,
+    ) {
+        MOCK_EVENT_BUFFER_SIZE.with(|c| c.set(size));
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_request_config_get_event_buffer_size(
+        _: *mut gpiod_request_config,
+    ) -> usize {
+        MOCK_EVENT_BUFFER_SIZE.with(Cell::get)
     }
 
     static GPIOD_CHIP_REQUEST_LINES_RESULT: AtomicBool = AtomicBool::new(false);
     #[no_mangle]
-    pub unsafe extern "C" fn gpiod_chip_request_lines(
-        _: *mut gpiod_chip,
-        _: *mut gpiod_request_config,
-        _: *mut gpiod_line_config,
-    ) -> *mut gpiod_line_request {
-        if GPIOD_CHIP_REQUEST_LINES_RESULT.load(Ordering::SeqCst) {
-            return 1 as *mut gpiod_line_request;
-        }
-        ptr::null_mut()
+    pub unsafe extern "C" fn gpiod_chip_request_lines(
+        _: *mut gpiod_chip,
+        _: *mut gpiod_request_config,
+        _: *mut gpiod_line_config,
+    ) -> *mut gpiod_line_request {
+        if GPIOD_CHIP_REQUEST_LINES_RESULT.load(Ordering::SeqCst) {
+            return 1 as *mut gpiod_line_request;
+        }
+        // Someone else has the line.
+        set_errno(libc::EBUSY);
+        ptr::null_mut()
+    }
+
+    // The mocked request always holds these lines, with values tracked per thread.
+    const MOCK_REQUESTED_OFFSETS: [Offset; 2] = [4, 17];
+
+    thread_local! {
+        // Mock result for the fallible gpiod_line_request_* functions
+        static GPIOD_LINE_REQUEST_RESULT: Cell<bool> = const { Cell::new(true) };
+        static MOCK_LINE_VALUES: RefCell<[gpiod_line_value; 2]> = const { RefCell::new([0; 2]) };
+        static REQUEST_RELEASED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn mock_line_index(offset: Offset) -> Option<usize> {
+        MOCK_REQUESTED_OFFSETS.iter().position(|&o| o == offset)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_get_chip_name(
+        _: *mut gpiod_line_request,
+    ) -> *const i8 {
+        b"gpiochip0\0".as_ptr() as *const i8
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_get_num_requested_lines(
+        _: *mut gpiod_line_request,
+    ) -> usize {
+        MOCK_REQUESTED_OFFSETS.len()
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_get_requested_offsets(
+        _: *mut gpiod_line_request,
+        offsets: *mut Offset,
+        max_offsets: usize,
+    ) -> usize {
+        let num = MOCK_REQUESTED_OFFSETS.len().min(max_offsets);
+        ptr::copy_nonoverlapping(MOCK_REQUESTED_OFFSETS.as_ptr(), offsets, num);
+        num
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_set_value(
+        _: *mut gpiod_line_request,
+        offset: Offset,
+        value: gpiod_line_value,
+    ) -> i32 {
+        match mock_line_index(offset) {
+            Some(i) if GPIOD_LINE_REQUEST_RESULT.with(Cell::get) => {
+                MOCK_LINE_VALUES.with(|v| v.borrow_mut()[i] = value);
+                0
+            }
+            _ => -1,
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_get_value(
+        _: *mut gpiod_line_request,
+        offset: Offset,
+    ) -> gpiod_line_value {
+        match mock_line_index(offset) {
+            Some(i) if GPIOD_LINE_REQUEST_RESULT.with(Cell::get) => {
+                MOCK_LINE_VALUES.with(|v| v.borrow()[i])
+            }
+            _ => gpiod_line_value_GPIOD_LINE_VALUE_ERROR,
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_set_values(
+        request: *mut gpiod_line_request,
+        values: *const gpiod_line_value,
+    ) -> i32 {
+        gpiod_line_request_set_values_subset(
+            request,
+            MOCK_REQUESTED_OFFSETS.len(),
+            MOCK_REQUESTED_OFFSETS.as_ptr(),
+            values,
+        )
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_get_values(
+        request: *mut gpiod_line_request,
+        values: *mut gpiod_line_value,
+    ) -> i32 {
+        gpiod_line_request_get_values_subset(
+            request,
+            MOCK_REQUESTED_OFFSETS.len(),
+            MOCK_REQUESTED_OFFSETS.as_ptr(),
+            values,
+        )
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_set_values_subset(
+        request: *mut gpiod_line_request,
+        num_values: usize,
+        offsets: *const Offset,
+        values: *const gpiod_line_value,
+    ) -> i32 {
+        for i in 0..num_values {
+            if gpiod_line_request_set_value(request, *offsets.add(i), *values.add(i)) != 0 {
+                return -1;
+            }
+        }
+        0
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_get_values_subset(
+        request: *mut gpiod_line_request,
+        num_values: usize,
+        offsets: *const Offset,
+        values: *mut gpiod_line_value,
+    ) -> i32 {
+        for i in 0..num_values {
+            let value = gpiod_line_request_get_value(request, *offsets.add(i));
+            if value == gpiod_line_value_GPIOD_LINE_VALUE_ERROR {
+                return -1;
+            }
+            *values.add(i) = value;
+        }
+        0
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_reconfigure_lines(
+        _: *mut gpiod_line_request,
+        _: *mut gpiod_line_config,
+    ) -> i32 {
+        if GPIOD_LINE_REQUEST_RESULT.with(Cell::get) {
+            return 0;
+        }
+        -1
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_release(_: *mut gpiod_line_request) {
+        REQUEST_RELEASED.with(|c| c.set(c.get() + 1));
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct MockEvent {
+        kind: gpiod_edge_event_type,
+        timestamp_ns: u64,
+        offset: Offset,
+        global_seqno: c_ulong,
+        line_seqno: c_ulong,
+    }
+
+    fn mock_edge(kind: gpiod_edge_event_type, timestamp_ns: u64) -> MockEvent {
+        MockEvent {
+            kind,
+            timestamp_ns,
+            offset: 4,
+            global_seqno: 0,
+            line_seqno: 0,
+        }
+    }
+
+    thread_local! {
+        // Events the mocked kernel has queued for the request, those last read into a buffer, and
+        // those copied out of a buffer.
+        static MOCK_PENDING_EVENTS: RefCell<Vec<MockEvent>> = const { RefCell::new(Vec::new()) };
+        static MOCK_BUFFER_EVENTS: RefCell<Vec<MockEvent>> = const { RefCell::new(Vec::new()) };
+        static MOCK_COPIED_EVENTS: RefCell<Vec<MockEvent>> = const { RefCell::new(Vec::new()) };
+        static EVENT_FREED: Cell<usize> = const { Cell::new(0) };
+        // Mock result for gpiod_edge_event_copy
+        static GPIOD_EDGE_EVENT_COPY_RESULT: Cell<bool> = const { Cell::new(true) };
+        static MOCK_BUFFER_CAPACITY: Cell<usize> = const { Cell::new(0) };
+        // Mock result for gpiod_edge_event_buffer_new
+        static GPIOD_EDGE_EVENT_BUFFER_CREATED: Cell<bool> = const { Cell::new(true) };
+        static BUFFER_FREED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_wait_edge_events(
+        _: *mut gpiod_line_request,
+        _: i64,
+    ) -> i32 {
+        if !GPIOD_LINE_REQUEST_RESULT.with(Cell::get) {
+            return -1;
+        }
+        MOCK_PENDING_EVENTS.with(|e| i32::from(!e.borrow().is_empty()))
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_line_request_read_edge_events(
+        _: *mut gpiod_line_request,
+        _: *mut gpiod_edge_event_buffer,
+        max_events: usize,
+    ) -> i32 {
+        if !GPIOD_LINE_REQUEST_RESULT.with(Cell::get) {
+            return -1;
+        }
+        let read: Vec<_> = MOCK_PENDING_EVENTS.with(|e| {
+            let mut pending = e.borrow_mut();
+            let num = pending.len().min(max_events);
+            pending.drain(..num).collect()
+        });
+        let num = read.len();
+        MOCK_BUFFER_EVENTS.with(|e| *e.borrow_mut() = read);
+        num as i32
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_buffer_new(
+        capacity: usize,
+    ) -> *mut gpiod_edge_event_buffer {
+        if !GPIOD_EDGE_EVENT_BUFFER_CREATED.with(Cell::get) {
+            return ptr::null_mut();
+        }
+        MOCK_BUFFER_CAPACITY.with(|c| c.set(if capacity == 0 { 64 } else { capacity }));
+        MOCK_BUFFER_EVENTS.with(|e| e.borrow_mut().clear());
+        1 as *mut gpiod_edge_event_buffer
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_buffer_get_capacity(
+        _: *mut gpiod_edge_event_buffer,
+    ) -> usize {
+        MOCK_BUFFER_CAPACITY.with(Cell::get)
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_buffer_get_num_events(
+        _: *mut gpiod_edge_event_buffer,
+    ) -> usize {
+        MOCK_BUFFER_EVENTS.with(|e| e.borrow().len())
+    }
+
+    // Events are handed out as their index into the buffer plus one, so they are never null.
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_buffer_get_event(
+        _: *mut gpiod_edge_event_buffer,
+        index: c_ulong,
+    ) -> *mut gpiod_edge_event {
+        (index as usize + 1) as *mut gpiod_edge_event
+    }
+
+    // Copied events are handed out from here on, so they can't be mistaken for buffered ones.
+    const MOCK_COPY_BASE: usize = 0x1000;
+
+    fn mock_event(event: *mut gpiod_edge_event) -> MockEvent {
+        match event as usize {
+            i if i > MOCK_COPY_BASE => {
+                MOCK_COPIED_EVENTS.with(|e| e.borrow()[i - MOCK_COPY_BASE - 1])
+            }
+            i => MOCK_BUFFER_EVENTS.with(|e| e.borrow()[i - 1]),
+        }
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_get_event_type(
+        event: *mut gpiod_edge_event,
+    ) -> gpiod_edge_event_type {
+        mock_event(event).kind
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_get_timestamp_ns(
+        event: *mut gpiod_edge_event,
+    ) -> u64 {
+        mock_event(event).timestamp_ns
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_get_line_offset(
+        event: *mut gpiod_edge_event,
+    ) -> Offset {
+        mock_event(event).offset
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_get_global_seqno(
+        event: *mut gpiod_edge_event,
+    ) -> c_ulong {
+        mock_event(event).global_seqno
+    }
+
+    #[no_mangle]
+    pub unsafe extern "C" fn gpiod_edge_event_get_line_seqno(
+        event: *mut gpiod_edge_event,
+    ) -> c_ulong {
+        mock_event(event).line_seqno
     }
 
-    static GPIOD_LINE_REQUEST_SET_VALUE_RESULT: AtomicBool = AtomicBool::new(false);
     #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_request_set_value(
-        _: *mut gpiod_line_request,
-        _: std::os::raw::c_uint,
-        _: gpiod_line_value,
-    ) -> i32 {
-        if GPIOD_LINE_REQUEST_SET_VALUE_RESULT.load(Ordering::SeqCst) {
-            return 0;
+    pub unsafe extern "C" fn gpiod_edge_event_copy(
+        event: *mut gpiod_edge_event,
+    ) -> *mut gpiod_edge_event {
+        if !GPIOD_EDGE_EVENT_COPY_RESULT.with(Cell::get) {
+            return ptr::null_mut();
         }
-        -1
+        let copy = mock_event(event);
+        let index = MOCK_COPIED_EVENTS.with(|e| {
+            let mut copied = e.borrow_mut();
+            copied.push(copy);
+            copied.len()
+        });
+        (MOCK_COPY_BASE + index) as *mut gpiod_edge_event
     }
 
-    static GPIOD_LINE_REQUEST_RECONFIGURE_LINES_RESULT: AtomicBool = AtomicBool::new(false);
     #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_request_reconfigure_lines(
-        _: *mut gpiod_line_request,
-        _: *mut gpiod_line_config,
-    ) -> i32 {
-        if GPIOD_LINE_REQUEST_RECONFIGURE_LINES_RESULT.load(Ordering::SeqCst) {
-            return 0;
-        }
-        -1
+    pub unsafe extern "C" fn gpiod_edge_event_free(_ptr: *mut gpiod_edge_event) {
+        EVENT_FREED.with(|c| c.set(c.get() + 1));
     }
 
-    static GPIOD_LINE_REQUEST_GET_VALUE_RESULT: AtomicBool = AtomicBool::new(false);
     #[no_mangle]
-    pub unsafe extern "C" fn gpiod_line_request_get_value(
-        _: *mut gpiod_line_request,
-        _: std::os::raw::c_uint,
-    ) -> i32 {
-        if GPIOD_LINE_REQUEST_GET_VALUE_RESULT.load(Ordering::SeqCst) {
-            return 1;
-        }
-        -1
+    pub unsafe extern "C" fn gpiod_edge_event_buffer_free(_ptr: *mut gpiod_edge_event_buffer) {
+        BUFFER_FREED.with(|c| c.set(c.get() + 1));
     }
 
     #[no_mangle]
     pub unsafe extern "C" fn gpiod_line_config_free(_ptr: *mut gpiod_line_config) {
-        CONFIG_FREED.fetch_add(1, Ordering::SeqCst);
+        CONFIG_FREED.with(|c| c.set(c.get() + 1));
     }
     #[no_mangle]
     pub unsafe extern "C" fn gpiod_line_settings_free(_ptr: *mut gpiod_line_settings) {
-        SETTINGS_FREED.fetch_add(1, Ordering::SeqCst);
+        SETTINGS_FREED.with(|c| c.set(c.get() + 1));
     }
     #[no_mangle]
     pub unsafe extern "C" fn gpiod_chip_info_free(_ptr: *mut gpiod_chip_info) {
-        INFO_FREED.fetch_add(1, Ordering::SeqCst);
+        INFO_FREED.with(|c| c.set(c.get() + 1));
     }
     #[no_mangle]
     pub unsafe extern "C" fn gpiod_chip_close(_ptr: *mut gpiod_chip) {
-        CHIP_FREED.fetch_add(1, Ordering::SeqCst);
+        CHIP_FREED.with(|c| c.set(c.get() + 1));
     }
 
-    #[test_case(b"dummy\0".as_ptr(); "create chip")]
-    #[test_case(ptr::null_mut(); "fail to create chip")]
+    #[test_case(b"dummy\0"; "create chip")]
+    #[test_case(b"\0"; "fail to create chip")]
     #[test]
-    fn test_gpio_chip_open(ptr: *const u8) {
-        let chip = Gpiod {}.chip(ptr);
-        if ptr.is_null() {
+    fn test_gpio_chip_open(path: &[u8]) {
+        let path = CStr::from_bytes_with_nul(path).unwrap();
+        let chip = Gpiod {}.chip(path);
+        if path.is_empty() {
             assert!(chip.is_err());
         } else {
             assert!(chip.is_ok());
         }
     }
 
-    #[test_case(ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_chip, false; "fail to get chip info")]
-    #[test_case(1 as *mut gpiod_chip, true; "get chip info")]
     #[test]
-    fn test_gpio_get_chip_info(chip: *mut gpiod_chip, desired: bool) {
+    fn test_chip_closed_on_drop() {
+        CHIP_FREED.with(|c| c.set(0));
+
+        let chip = Gpiod {}.chip(CStr::from_bytes_with_nul(b"dummy\0").unwrap());
+        assert!(chip.is_ok());
+        assert_eq!(CHIP_FREED.with(Cell::get), 0);
+
+        drop(chip);
+        assert_eq!(CHIP_FREED.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_chip_failed_open_is_not_closed() {
+        CHIP_FREED.with(|c| c.set(0));
+
+        let chip = Gpiod {}.chip(CStr::from_bytes_with_nul(b"\0").unwrap());
+        assert!(chip.is_err());
+        drop(chip);
+
+        assert_eq!(CHIP_FREED.with(Cell::get), 0);
+    }
+
+    #[test]
+    fn test_chip_open_error() {
+        let Err(e) = Gpiod {}.chip(CStr::from_bytes_with_nul(b"\0").unwrap()) else {
+            panic!("expected an error");
+        };
+        assert!(matches!(&e, GpiodError::OpenChip { path, .. } if path == Path::new("")));
+        assert_eq!(e.os_error().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_chip_line_offset_from_name_fails() {
+        let chip = Gpiod {}
+            .chip(CStr::from_bytes_with_nul(b"/dev/gpiochip-broken\0").unwrap())
+            .unwrap();
+        let e = chip.line_offset_from_name("GPIO99").unwrap_err();
+        assert!(matches!(&e, GpiodError::FindLine { name, .. } if name == "GPIO99"));
+        assert_eq!(e.os_error().unwrap().raw_os_error(), Some(libc::EIO));
+    }
+
+    #[test]
+    fn test_os_error_absent() {
+        assert!(GpiodError::LineNotFound("GPIO99".to_string())
+            .os_error()
+            .is_none());
+    }
+
+    #[test]
+    fn test_chip_path_and_fd() {
+        let chip = mock_chip();
+        assert_eq!(chip.path().unwrap(), "/dev/gpiochip0");
+        assert_eq!(chip.fd(), 3);
+    }
+
+    #[test_case("GPIO4", Some(4); "known line")]
+    #[test_case("GPIO99", None; "unknown line")]
+    #[test_case("GPIO\0", None; "name with NUL")]
+    #[test]
+    fn test_chip_line_offset_from_name(name: &str, expected: Option<Offset>) {
+        let chip = mock_chip();
+        match expected {
+            Some(offset) => assert_eq!(chip.line_offset_from_name(name).unwrap(), offset),
+            None => assert!(matches!(
+                chip.line_offset_from_name(name),
+                Err(GpiodError::LineNotFound(n)) if n == name
+            )),
+        }
+    }
+
+    #[test]
+    fn test_chip_line_info() {
+        let chip = mock_chip();
+
+        let info = chip.line_info(4).unwrap();
+        assert_eq!(info.offset(), 4);
+        assert_eq!(info.name().as_deref(), Some("GPIO4"));
+        assert!(info.is_used());
+        assert_eq!(info.consumer().as_deref(), Some("dht22_reader"));
+        assert_eq!(info.direction().unwrap(), Direction::Input);
+        assert_eq!(info.edge_detection().unwrap(), Edge::Both);
+        assert_eq!(info.bias().unwrap(), Bias::Disabled);
+        assert_eq!(info.drive().unwrap(), Drive::OpenDrain);
+        assert!(!info.is_active_low());
+        assert_eq!(info.debounce_period(), Duration::ZERO);
+        assert_eq!(info.event_clock().unwrap(), EventClock::Monotonic);
+
+        let info = chip.line_info(17).unwrap();
+        assert_eq!(info.name(), None);
+        assert!(!info.is_used());
+        assert_eq!(info.consumer(), None);
+        assert_eq!(info.debounce_period(), Duration::from_micros(250));
+    }
+
+    #[test]
+    fn test_chip_line_info_out_of_range() {
+        let chip = mock_chip();
+        let Err(err) = chip.line_info(54) else {
+            panic!("expected an error");
+        };
+        assert!(matches!(
+            &err,
+            GpiodError::GetLineInfo { chip, offset: 54, .. } if chip == Path::new("/dev/gpiochip0")
+        ));
+        assert_eq!(err.os_error().unwrap().raw_os_error(), Some(libc::EINVAL));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to get line info for offset 54 on /dev/gpiochip0: {}",
+                io::Error::from_raw_os_error(libc::EINVAL)
+            )
+        );
+    }
+
+    #[test]
+    fn test_line_info_freed_on_drop() {
+        LINE_INFO_FREED.with(|c| c.set(0));
+        let chip = mock_chip();
+
+        let info = chip.line_info(4).unwrap();
+        assert_eq!(LINE_INFO_FREED.with(Cell::get), 0);
+
+        drop(info);
+        assert_eq!(LINE_INFO_FREED.with(Cell::get), 1);
+    }
+
+    #[test_case(4, true; "watch line")]
+    #[test_case(54, false; "fail to watch line")]
+    #[test]
+    fn test_chip_watch_line_info(offset: Offset, desired: bool) {
+        let chip = mock_chip();
+        let result = chip.watch_line_info(offset);
+        assert_eq!(result.is_err(), !desired);
+        if desired {
+            assert_eq!(result.unwrap().offset(), offset);
+        }
+        assert_eq!(chip.unwatch_line_info(offset).is_err(), !desired);
+    }
+
+    #[test]
+    fn test_chip_read_info_events() {
+        INFO_EVENT_FREED.with(|c| c.set(0));
+        LINE_INFO_FREED.with(|c| c.set(0));
+        MOCK_INFO_EVENTS.with(|e| {
+            *e.borrow_mut() = vec![
+                (
+                    gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_REQUESTED,
+                    1_000,
+                    4,
+                ),
+                (
+                    gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_CONFIG_CHANGED,
+                    2_000,
+                    4,
+                ),
+                (
+                    gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_RELEASED,
+                    3_000,
+                    4,
+                ),
+            ]
+        });
+        let chip = mock_chip();
+
+        let mut kinds = vec![];
+        while chip.wait_info_event(Duration::ZERO).unwrap() {
+            let event = chip.read_info_event().unwrap();
+            assert_eq!(event.line_info.offset(), 4);
+            assert_eq!(event.line_info.consumer().as_deref(), Some("dht22_reader"));
+            kinds.push((event.kind, event.timestamp.as_nanos()));
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                (InfoEventKind::Requested, 1_000),
+                (InfoEventKind::ConfigChanged, 2_000),
+                (InfoEventKind::Released, 3_000),
+            ]
+        );
+        // Each event is freed as soon as it's read, and each copied line info once dropped.
+        assert_eq!(INFO_EVENT_FREED.with(Cell::get), 3);
+        assert_eq!(LINE_INFO_FREED.with(Cell::get), 3);
+    }
+
+    #[test]
+    fn test_chip_read_info_event_fails() {
+        MOCK_INFO_EVENTS.with(|e| e.borrow_mut().clear());
+        let chip = mock_chip();
+        assert!(matches!(
+            chip.read_info_event(),
+            Err(GpiodError::ReadInfoEvent { .. })
+        ));
+    }
+
+    #[test]
+    fn test_chip_read_info_event_copy_fails() {
+        INFO_EVENT_FREED.with(|c| c.set(0));
+        MOCK_INFO_EVENTS.with(|e| {
+            *e.borrow_mut() = vec![(gpiod_info_event_type_GPIOD_INFO_EVENT_LINE_REQUESTED, 0, 54)]
+        });
+        let chip = mock_chip();
+        let Err(err) = chip.read_info_event() else {
+            panic!("expected the copy to fail");
+        };
+        assert!(matches!(err, GpiodError::ReadInfoEvent { .. }));
+        // The copy's errno, not whatever freeing the event left behind.
+        assert_eq!(
+            err.os_error().and_then(io::Error::raw_os_error),
+            Some(libc::ENOMEM)
+        );
+        assert_eq!(INFO_EVENT_FREED.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_chip_read_info_event_unknown_kind() {
+        LINE_INFO_FREED.with(|c| c.set(0));
+        MOCK_INFO_EVENTS.with(|e| *e.borrow_mut() = vec![(9, 0, 4)]);
+        let chip = mock_chip();
+        assert!(matches!(
+            chip.read_info_event(),
+            Err(GpiodError::UnknownValue("info event type", 9))
+        ));
+        assert_eq!(LINE_INFO_FREED.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_enum_display_matches_serialized() {
+        assert_eq!(Bias::PullDown.to_string(), "pull-down");
+        assert_eq!(
+            serde_json::to_value(Bias::PullDown).unwrap(),
+            Bias::PullDown.to_string()
+        );
+        assert_eq!(Direction::AsIs.to_string(), "as-is");
+        assert_eq!(Drive::OpenSource.to_string(), "open-source");
+        assert_eq!(EventClock::Hte.to_string(), "hte");
+    }
+
+    // Makes a directory of empty files with the given names, standing in for `/dev`.
+    fn mock_dev_dir(test: &str, names: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dht22_reader_{}_{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in names {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_chip_paths() {
+        let dir = mock_dev_dir(
+            "chip_paths",
+            &[
+                "gpiochip10",
+                "gpiochip2",
+                "gpiochip0",
+                "gpiochip-fake",
+                "ttyAMA0",
+            ],
+        );
+        let names: Vec<_> = chip_paths(&dir)
+            .into_iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(names, vec!["gpiochip0", "gpiochip2", "gpiochip10"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_chip_paths_missing_dir() {
+        assert!(chip_paths(Path::new("/nonexistent/dht22_reader")).is_empty());
+    }
+
+    #[test_case("GPIO4", Some(("gpiochip0", 4)); "first chip")]
+    #[test_case("GPIO17", Some(("gpiochip10", 17)); "later chip")]
+    #[test_case("GPIO99", None; "no chip")]
+    #[test]
+    fn test_find_line(name: &str, expected: Option<(&str, Offset)>) {
+        let dir = mock_dev_dir(&format!("find_line_{name}"), &["gpiochip10", "gpiochip0"]);
+        let result = Gpiod {}.find_line(&dir, name);
+        match expected {
+            Some((chip, offset)) => assert_eq!(result.unwrap(), (dir.join(chip), offset)),
+            None => assert!(matches!(result, Err(GpiodError::LineNotFound(n)) if n == name)),
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test_case(false; "fail to get chip info")]
+    #[test_case(true; "get chip info")]
+    #[test]
+    fn test_gpio_get_chip_info(desired: bool) {
         GPIOD_CHIP_GET_INFO_RESULT.store(desired, Ordering::SeqCst);
-        let info = Gpiod {}.info(chip);
+        INFO_FREED.with(|c| c.set(0));
+
+        let chip = mock_chip();
+        let info = chip.info();
         assert_eq!(info.is_err(), !desired);
+
+        drop(info);
+        assert_eq!(INFO_FREED.with(Cell::get), usize::from(desired));
     }
 
-    #[test_case(ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_chip_info, false; "fail to get chip name")]
-    #[test_case(1 as *mut gpiod_chip_info, true; "get chip name")]
+    #[test_case(false; "fail to get chip name")]
+    #[test_case(true; "get chip name")]
     #[test]
-    fn test_gpio_get_chip_name(info: *mut gpiod_chip_info, desired: bool) {
+    fn test_gpio_get_chip_name(desired: bool) {
         GPIOD_CHIP_GET_NAME_RESULT.store(desired, Ordering::SeqCst);
-        let name = Gpiod {}.name(info);
+        let info = ChipInfo {
+            ptr: 1 as *mut gpiod_chip_info,
+        };
+        let name = info.name();
         assert_eq!(name.is_err(), !desired);
     }
 
+    #[test]
+    fn test_chip_info_label_and_num_lines() {
+        let info = ChipInfo {
+            ptr: 1 as *mut gpiod_chip_info,
+        };
+        assert_eq!(info.label().unwrap(), "pinctrl-rp1");
+        assert_eq!(info.num_lines(), 54);
+    }
+
+    fn mock_settings() -> LineSettings {
+        LineSettings {
+            ptr: 1 as *mut gpiod_line_settings,
+        }
+    }
+
     #[test_case(false; "fail to create settings")]
     #[test_case(true; "create settings")]
     #[test]
     fn test_gpio_create_settings(desired: bool) {
         GPIOD_SETTINGS_CREATED.store(desired, Ordering::SeqCst);
-        let settings = Gpiod {}.settings();
+        let settings = LineSettings::new();
         assert_eq!(settings.is_err(), !desired);
     }
 
-    #[test_case(ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_settings, false; "fail to set drive")]
-    #[test_case(1 as *mut gpiod_line_settings, true; "set drive")]
     #[test]
-    fn test_gpio_set_bias(settings: *mut gpiod_line_settings, desired: bool) {
-        GPIOD_SETTINGS_BIAS_SET.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.settings_set_bias(settings, gpiod_line_bias_GPIOD_LINE_BIAS_PULL_UP);
+    fn test_settings_freed_on_drop() {
+        SETTINGS_FREED.with(|c| c.set(0));
+
+        let settings = mock_settings();
+        assert_eq!(SETTINGS_FREED.with(Cell::get), 0);
+
+        drop(settings);
+        assert_eq!(SETTINGS_FREED.with(Cell::get), 1);
+    }
+
+    #[test_case(false; "fail to set bias")]
+    #[test_case(true; "set bias")]
+    #[test]
+    fn test_gpio_set_bias(desired: bool) {
+        GPIOD_SETTINGS_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_settings().set_bias(Bias::PullUp).map(|_| ());
         assert_eq!(result.is_err(), !desired);
     }
 
-    #[test_case(ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_settings, false; "fail to set drive")]
-    #[test_case(1 as *mut gpiod_line_settings, true; "set drive")]
+    #[test_case(false; "fail to set drive")]
+    #[test_case(true; "set drive")]
     #[test]
-    fn test_gpio_set_drive(settings: *mut gpiod_line_settings, desired: bool) {
-        GPIOD_SETTINGS_DRIVE_SET.store(desired, Ordering::SeqCst);
-        let result =
-            Gpiod {}.settings_set_drive(settings, gpiod_line_drive_GPIOD_LINE_DRIVE_PUSH_PULL);
+    fn test_gpio_set_drive(desired: bool) {
+        GPIOD_SETTINGS_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_settings().set_drive(Drive::PushPull).map(|_| ());
+        if desired {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(
+                result,
+                Err(GpiodError::SetDrive {
+                    drive: Drive::PushPull,
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test_case(false; "fail to set direction")]
+    #[test_case(true; "set direction")]
+    #[test]
+    fn test_gpio_set_direction(desired: bool) {
+        GPIOD_SETTINGS_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_settings().set_direction(Direction::Input).map(|_| ());
+        assert_eq!(result.is_err(), !desired);
+    }
+
+    #[test_case(false; "fail to set edge detection")]
+    #[test_case(true; "set edge detection")]
+    #[test]
+    fn test_gpio_set_edge_detection(desired: bool) {
+        GPIOD_SETTINGS_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_settings().set_edge_detection(Edge::Both).map(|_| ());
+        assert_eq!(result.is_err(), !desired);
+    }
+
+    #[test_case(false; "fail to set event clock")]
+    #[test_case(true; "set event clock")]
+    #[test]
+    fn test_gpio_set_event_clock(desired: bool) {
+        GPIOD_SETTINGS_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_settings()
+            .set_event_clock(EventClock::Realtime)
+            .map(|_| ());
         assert_eq!(result.is_err(), !desired);
     }
 
-    #[test_case(ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_settings, false; "fail to set direction")]
-    #[test_case(1 as *mut gpiod_line_settings, true; "set direction")]
+    #[test_case(false; "fail to set output value")]
+    #[test_case(true; "set output value")]
     #[test]
-    fn test_gpio_set_direction(settings: *mut gpiod_line_settings, desired: bool) {
-        GPIOD_SETTINGS_DIRECTION_SET.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.settings_set_direction(settings, 1);
+    fn test_gpio_set_output_value(desired: bool) {
+        GPIOD_SETTINGS_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_settings().set_output_value(Value::Active).map(|_| ());
         assert_eq!(result.is_err(), !desired);
     }
 
+    #[test]
+    fn test_settings_round_trip() {
+        let mut settings = mock_settings();
+        settings
+            .set_direction(Direction::Output)
+            .unwrap()
+            .set_edge_detection(Edge::Falling)
+            .unwrap()
+            .set_bias(Bias::PullDown)
+            .unwrap()
+            .set_drive(Drive::OpenDrain)
+            .unwrap()
+            .set_active_low(true)
+            .set_debounce_period(Duration::from_micros(1500))
+            .set_event_clock(EventClock::Hte)
+            .unwrap()
+            .set_output_value(Value::Active)
+            .unwrap();
+
+        assert_eq!(settings.direction().unwrap(), Direction::Output);
+        assert_eq!(settings.edge_detection().unwrap(), Edge::Falling);
+        assert_eq!(settings.bias().unwrap(), Bias::PullDown);
+        assert_eq!(settings.drive().unwrap(), Drive::OpenDrain);
+        assert!(settings.active_low());
+        assert_eq!(settings.debounce_period(), Duration::from_micros(1500));
+        assert_eq!(settings.event_clock().unwrap(), EventClock::Hte);
+        assert_eq!(settings.output_value().unwrap(), Value::Active);
+    }
+
+    #[test]
+    fn test_settings_unknown_value() {
+        MOCK_SETTINGS.with(|s| s.borrow_mut().direction = 42);
+        let result = mock_settings().direction();
+        assert!(matches!(
+            result,
+            Err(GpiodError::UnknownValue("direction", 42))
+        ));
+    }
+
+    fn mock_config() -> LineConfig {
+        MOCK_CONFIG_OFFSETS.with(|o| o.borrow_mut().clear());
+        LineConfig {
+            ptr: 1 as *mut gpiod_line_config,
+        }
+    }
+
     #[test_case(false; "fail to create config")]
     #[test_case(true; "create config")]
     #[test]
     fn test_gpio_create_config(desired: bool) {
         GPIOD_CONFIG_CREATED.store(desired, Ordering::SeqCst);
-        let config = Gpiod {}.config();
+        let config = LineConfig::new();
         assert_eq!(config.is_err(), !desired);
     }
 
-    #[test_case(ptr::null_mut(), ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_config, 1 as *mut gpiod_line_settings, false; "fail to add settings")]
-    #[test_case(1 as *mut gpiod_line_config, 1 as *mut gpiod_line_settings, true; "add settings")]
     #[test]
-    fn test_gpio_add_settings(
-        config: *mut gpiod_line_config,
-        settings: *mut gpiod_line_settings,
-        desired: bool,
-    ) {
-        GPIOD_CONFIG_ADD_SETTINGS_RESULT.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.config_add_settings(config, settings);
+    fn test_config_freed_on_drop() {
+        CONFIG_FREED.with(|c| c.set(0));
+
+        let config = mock_config();
+        assert_eq!(CONFIG_FREED.with(Cell::get), 0);
+
+        drop(config);
+        assert_eq!(CONFIG_FREED.with(Cell::get), 1);
+    }
+
+    #[test_case(false; "fail to add settings")]
+    #[test_case(true; "add settings")]
+    #[test]
+    fn test_gpio_add_settings(desired: bool) {
+        GPIOD_CONFIG_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_config()
+            .add_line_settings(&[4], &mock_settings())
+            .map(|_| ());
         assert_eq!(result.is_err(), !desired);
     }
 
-    #[test_case(ptr::null_mut(), ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_chip, 1 as *mut gpiod_line_config, false; "fail to request lines")]
-    #[test_case(1 as *mut gpiod_chip, 1 as *mut gpiod_line_config, true; "request lines")]
     #[test]
-    fn test_gpio_chip_request_lines(
-        chip: *mut gpiod_chip,
-        line_cfg: *mut gpiod_line_config,
-        desired: bool,
-    ) {
+    fn test_config_multiple_offsets() {
+        GPIOD_CONFIG_SET_RESULT.with(|c| c.set(true));
+        let mut config = mock_config();
+        config
+            .add_line_settings(&[4, 17], &mock_settings())
+            .unwrap()
+            .add_line_settings(&[27], &mock_settings())
+            .unwrap();
+
+        assert_eq!(config.configured_offsets(), vec![4, 17, 27]);
+        assert!(config.line_settings(17).is_ok());
+        assert!(matches!(
+            config.line_settings(22),
+            Err(GpiodError::GetLineSettings { offset: 22, .. })
+        ));
+
+        config.reset();
+        assert!(config.configured_offsets().is_empty());
+    }
+
+    #[test_case(false; "fail to set output values")]
+    #[test_case(true; "set output values")]
+    #[test]
+    fn test_config_set_output_values(desired: bool) {
+        GPIOD_CONFIG_SET_RESULT.with(|c| c.set(desired));
+        let result = mock_config()
+            .set_output_values(&[Value::Active, Value::Inactive])
+            .map(|_| ());
+        assert_eq!(result.is_err(), !desired);
+        if desired {
+            assert_eq!(
+                MOCK_CONFIG_OUTPUT_VALUES.with(|v| v.borrow().clone()),
+                [1, 0]
+            );
+        }
+    }
+
+    #[test_case(false; "fail to request lines")]
+    #[test_case(true; "request lines")]
+    #[test]
+    fn test_gpio_chip_request_lines(desired: bool) {
         GPIOD_CHIP_REQUEST_LINES_RESULT.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.chip_request_lines(chip, line_cfg);
+        let chip = mock_chip();
+        let req_cfg = RequestConfig::new().unwrap();
+        let result = Gpiod {}.chip_request_lines(&chip, &req_cfg, &mock_config());
         assert_eq!(result.is_err(), !desired);
+        if let Err(e) = result {
+            assert!(
+                matches!(&e, GpiodError::RequestLines { chip, .. } if chip == Path::new("/dev/gpiochip0"))
+            );
+            assert_eq!(e.os_error().unwrap().kind(), io::ErrorKind::ResourceBusy);
+        }
     }
 
-    #[test_case(ptr::null_mut(), 0, 1, false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_request, 0, 1, false; "fail to set value")]
-    #[test_case(1 as *mut gpiod_line_request, 0, 1, true; "set value")]
+    #[test_case(false; "fail to create request config")]
+    #[test_case(true; "create request config")]
     #[test]
-    fn test_gpio_line_request_set_value(
-        request: *mut gpiod_line_request,
-        offset: std::os::raw::c_uint,
-        value: gpiod_line_value,
-        desired: bool,
-    ) {
-        GPIOD_LINE_REQUEST_SET_VALUE_RESULT.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.line_request_set_value(request, offset, value);
+    fn test_gpio_create_request_config(desired: bool) {
+        GPIOD_REQUEST_CONFIG_CREATED.with(|c| c.set(desired));
+        let config = RequestConfig::new();
+        assert_eq!(config.is_err(), !desired);
+        if desired {
+            assert_eq!(config.unwrap().consumer().as_deref(), Some(CONSUMER));
+        }
+    }
+
+    #[test]
+    fn test_request_config_freed_on_drop() {
+        REQUEST_CONFIG_FREED.with(|c| c.set(0));
+
+        let config = RequestConfig::new().unwrap();
+        assert_eq!(REQUEST_CONFIG_FREED.with(Cell::get), 0);
+
+        drop(config);
+        assert_eq!(REQUEST_CONFIG_FREED.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_request_config_with_label() {
+        let config = RequestConfig::with_label("greenhouse").unwrap();
+        assert_eq!(
+            config.consumer().as_deref(),
+            Some("dht22_reader:greenhouse")
+        );
+    }
+
+    #[test]
+    fn test_request_config_invalid_consumer() {
+        let result = RequestConfig::with_label("green\0house");
+        assert!(matches!(result, Err(GpiodError::InvalidConsumer(_))));
+    }
+
+    #[test]
+    fn test_request_config_event_buffer_size() {
+        let mut config = RequestConfig::new().unwrap();
+        config.set_event_buffer_size(128);
+        assert_eq!(config.event_buffer_size(), 128);
+    }
+
+    fn mock_request() -> LineRequest {
+        MOCK_LINE_VALUES.with(|v| *v.borrow_mut() = [0; 2]);
+        LineRequest {
+            ptr: 1 as *mut gpiod_line_request,
+            chip: PathBuf::from("/dev/gpiochip0"),
+        }
+    }
+
+    #[test]
+    fn test_request_released_on_drop() {
+        REQUEST_RELEASED.with(|c| c.set(0));
+
+        let request = mock_request();
+        assert_eq!(REQUEST_RELEASED.with(Cell::get), 0);
+
+        drop(request);
+        assert_eq!(REQUEST_RELEASED.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_request_chip_name_and_offsets() {
+        let request = mock_request();
+        assert_eq!(request.chip_name().unwrap(), "gpiochip0");
+        assert_eq!(request.requested_offsets(), vec![4, 17]);
+    }
+
+    #[test_case(4, false; "fail to set value")]
+    #[test_case(4, true; "set value")]
+    #[test_case(5, true; "fail on unrequested offset")]
+    #[test]
+    fn test_gpio_line_request_set_value(offset: Offset, desired: bool) {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(desired));
+        let mut request = mock_request();
+        let result = request.set_value(offset, Value::Active).map(|_| ());
+        let requested = MOCK_REQUESTED_OFFSETS.contains(&offset);
+        assert_eq!(result.is_err(), !(desired && requested));
+    }
+
+    #[test_case(4, false; "fail to get value")]
+    #[test_case(4, true; "get value")]
+    #[test_case(5, true; "fail on unrequested offset")]
+    #[test]
+    fn test_gpio_line_request_get_value(offset: Offset, desired: bool) {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(desired));
+        let result = mock_request().value(offset);
+        let requested = MOCK_REQUESTED_OFFSETS.contains(&offset);
+        assert_eq!(result.is_err(), !(desired && requested));
+        if desired && requested {
+            assert_eq!(result.unwrap(), Value::Inactive);
+        }
+    }
+
+    #[test]
+    fn test_request_values_round_trip() {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(true));
+        let mut request = mock_request();
+
+        request.set_value(17, Value::Active).unwrap();
+        assert_eq!(request.value(17).unwrap(), Value::Active);
+        assert_eq!(
+            request.values().unwrap(),
+            vec![Value::Inactive, Value::Active]
+        );
+
+        request
+            .set_values(&[Value::Active, Value::Inactive])
+            .unwrap();
+        assert_eq!(
+            request.values_subset(&[17, 4]).unwrap(),
+            vec![Value::Inactive, Value::Active]
+        );
+
+        request.set_values_subset(&[17], &[Value::Active]).unwrap();
+        assert_eq!(
+            request.values().unwrap(),
+            vec![Value::Active, Value::Active]
+        );
+    }
+
+    #[test]
+    fn test_request_set_values_wrong_count() {
+        let result = mock_request().set_values(&[Value::Active]).map(|_| ());
+        assert!(matches!(
+            result,
+            Err(GpiodError::ValueCount {
+                expected: 2,
+                actual: 1
+            })
+        ));
+    }
+
+    #[test_case(false; "fail to reconfigure lines")]
+    #[test_case(true; "reconfigure lines")]
+    #[test]
+    fn test_gpio_line_request_reconfigure_lines(desired: bool) {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(desired));
+        let result = mock_request().reconfigure(&mock_config()).map(|_| ());
         assert_eq!(result.is_err(), !desired);
     }
 
-    #[test_case(ptr::null_mut(), ptr::null_mut(), false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_request, 1 as *mut gpiod_line_config, false; "fail to reconfigure lines")]
-    #[test_case(1 as *mut gpiod_line_request, 1 as *mut gpiod_line_config, true; "reconfigure lines")]
+    const RISING: gpiod_edge_event_type = gpiod_edge_event_type_GPIOD_EDGE_EVENT_RISING_EDGE;
+    const FALLING: gpiod_edge_event_type = gpiod_edge_event_type_GPIOD_EDGE_EVENT_FALLING_EDGE;
+
+    #[test_case(false; "fail to create edge event buffer")]
+    #[test_case(true; "create edge event buffer")]
     #[test]
-    fn test_gpio_line_request_reconfigure_lines(
-        request: *mut gpiod_line_request,
-        config: *mut gpiod_line_config,
-        desired: bool,
-    ) {
-        GPIOD_LINE_REQUEST_RECONFIGURE_LINES_RESULT.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.line_request_reconfigure_lines(request, config);
+    fn test_gpio_create_edge_event_buffer(desired: bool) {
+        GPIOD_EDGE_EVENT_BUFFER_CREATED.with(|c| c.set(desired));
+        let result = EdgeEventBuffer::new(16);
         assert_eq!(result.is_err(), !desired);
+        if desired {
+            assert_eq!(result.unwrap().capacity(), 16);
+        }
     }
 
-    #[test_case(ptr::null_mut(), 0, false; "fail on null ptr input")]
-    #[test_case(1 as *mut gpiod_line_request, 0, false; "fail to get value")]
-    #[test_case(1 as *mut gpiod_line_request, 0, true; "get value")]
     #[test]
-    fn test_gpio_line_request_get_value(
-        request: *mut gpiod_line_request,
-        offset: std::os::raw::c_uint,
-        desired: bool,
-    ) {
-        GPIOD_LINE_REQUEST_GET_VALUE_RESULT.store(desired, Ordering::SeqCst);
-        let result = Gpiod {}.line_request_get_value(request, offset);
+    fn test_edge_event_buffer_freed_on_drop() {
+        GPIOD_EDGE_EVENT_BUFFER_CREATED.with(|c| c.set(true));
+        BUFFER_FREED.with(|c| c.set(0));
+
+        let buffer = EdgeEventBuffer::new(0).unwrap();
+        assert_eq!(BUFFER_FREED.with(Cell::get), 0);
+
+        drop(buffer);
+        assert_eq!(BUFFER_FREED.with(Cell::get), 1);
+    }
+
+    #[test_case(false, vec![]; "fail to wait for edge events")]
+    #[test_case(true, vec![]; "timeout")]
+    #[test_case(true, vec![mock_edge(RISING, 1)]; "events pending")]
+    #[test]
+    fn test_gpio_line_request_wait_edge_events(desired: bool, pending: Vec<MockEvent>) {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(desired));
+        let ready = !pending.is_empty();
+        MOCK_PENDING_EVENTS.with(|e| *e.borrow_mut() = pending);
+
+        let result = mock_request().wait_edge_events(Duration::from_millis(1));
         assert_eq!(result.is_err(), !desired);
         if desired {
-            assert!(result.unwrap()); // hardcoded value from mock
+            assert_eq!(result.unwrap(), ready);
         }
     }
 
     #[test]
-    fn test_cleanup_invokes_all_free_functions() {
-        // Reset counters.
-        CONFIG_FREED.store(0, Ordering::SeqCst);
-        SETTINGS_FREED.store(0, Ordering::SeqCst);
-        INFO_FREED.store(0, Ordering::SeqCst);
-        CHIP_FREED.store(0, Ordering::SeqCst);
+    fn test_request_read_edge_events() {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(true));
+        GPIOD_EDGE_EVENT_BUFFER_CREATED.with(|c| c.set(true));
+        MOCK_PENDING_EVENTS.with(|e| {
+            *e.borrow_mut() = vec![
+                mock_edge(FALLING, 1_000),
+                mock_edge(RISING, 81_000),
+                mock_edge(FALLING, 161_000),
+            ]
+        });
+        let mut request = mock_request();
+        let mut buffer = EdgeEventBuffer::new(2).unwrap();
 
-        // Pass dummy non-null pointers.
-        cleanup(
-            Some(1 as *mut gpiod_chip),
-            Some(1 as *mut gpiod_chip_info),
-            Some(1 as *mut gpiod_line_settings),
-            Some(1 as *mut gpiod_line_config),
-        );
+        // Only as many events as the buffer holds are read at once.
+        assert_eq!(request.read_edge_events(&mut buffer).unwrap(), 2);
+        assert_eq!(buffer.len(), 2);
+        let event = buffer.event(1).unwrap();
+        assert_eq!(event.kind().unwrap(), EdgeKind::Rising);
+        assert_eq!(event.timestamp(), Duration::from_micros(81));
+        assert!(buffer.event(2).is_none());
+
+        assert_eq!(request.read_edge_events(&mut buffer).unwrap(), 1);
+        let event = buffer.event(0).unwrap();
+        assert_eq!(event.kind().unwrap(), EdgeKind::Falling);
+        assert_eq!(event.timestamp(), Duration::from_micros(161));
+    }
+
+    #[test]
+    fn test_request_read_edge_events_fails() {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(false));
+        GPIOD_EDGE_EVENT_BUFFER_CREATED.with(|c| c.set(true));
+        let mut buffer = EdgeEventBuffer::new(2).unwrap();
+        let result = mock_request().read_edge_events(&mut buffer);
+        assert!(matches!(result, Err(GpiodError::ReadEdgeEvents { .. })));
+    }
+
+    #[test]
+    fn test_edge_event_unknown_kind() {
+        GPIOD_EDGE_EVENT_BUFFER_CREATED.with(|c| c.set(true));
+        let buffer = EdgeEventBuffer::new(1).unwrap();
+        MOCK_BUFFER_EVENTS.with(|e| *e.borrow_mut() = vec![mock_edge(7, 0)]);
+        assert!(matches!(
+            buffer.event(0).unwrap().kind(),
+            Err(GpiodError::UnknownValue("edge event type", 7))
+        ));
+    }
+
+    fn mock_buffer(events: Vec<MockEvent>) -> EdgeEventBuffer {
+        GPIOD_LINE_REQUEST_RESULT.with(|c| c.set(true));
+        GPIOD_EDGE_EVENT_BUFFER_CREATED.with(|c| c.set(true));
+        let mut buffer = EdgeEventBuffer::new(events.len()).unwrap();
+        MOCK_PENDING_EVENTS.with(|e| *e.borrow_mut() = events);
+        mock_request().read_edge_events(&mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_edge_event_buffer_iter() {
+        let buffer = mock_buffer(vec![
+            MockEvent {
+                kind: FALLING,
+                timestamp_ns: 1_000,
+                offset: 4,
+                global_seqno: 7,
+                line_seqno: 3,
+            },
+            MockEvent {
+                kind: RISING,
+                timestamp_ns: 81_000,
+                offset: 17,
+                global_seqno: 8,
+                line_seqno: 1,
+            },
+        ]);
+
+        let mut events = buffer.iter();
+        assert_eq!(events.size_hint(), (2, Some(2)));
+
+        let event = events.next().unwrap();
+        assert_eq!(event.kind().unwrap(), EdgeKind::Falling);
+        assert_eq!(event.timestamp(), Duration::from_micros(1));
+        assert_eq!(event.line_offset(), 4);
+        assert_eq!(event.global_seqno(), 7);
+        assert_eq!(event.line_seqno(), 3);
+
+        let event = events.next().unwrap();
+        assert_eq!(event.kind().unwrap(), EdgeKind::Rising);
+        assert_eq!(event.line_offset(), 17);
+        assert_eq!(event.global_seqno(), 8);
+        assert_eq!(event.line_seqno(), 1);
 
-        assert_eq!(CONFIG_FREED.load(Ordering::SeqCst), 1);
-        assert_eq!(SETTINGS_FREED.load(Ordering::SeqCst), 1);
-        assert_eq!(INFO_FREED.load(Ordering::SeqCst), 1);
-        assert_eq!(CHIP_FREED.load(Ordering::SeqCst), 1);
+        assert!(events.next().is_none());
+        assert_eq!(events.size_hint(), (0, Some(0)));
     }
 
     #[test]
-    fn test_cleanup_handles_none() {
-        // Reset counters.
-        CONFIG_FREED.store(0, Ordering::SeqCst);
-        SETTINGS_FREED.store(0, Ordering::SeqCst);
-        INFO_FREED.store(0, Ordering::SeqCst);
-        CHIP_FREED.store(0, Ordering::SeqCst);
+    fn test_edge_event_buffer_into_iter() {
+        let buffer = mock_buffer(vec![mock_edge(RISING, 10), mock_edge(FALLING, 37)]);
+        let mut timestamps = vec![];
+        for event in &buffer {
+            timestamps.push(event.timestamp().as_nanos());
+        }
+        assert_eq!(timestamps, vec![10, 37]);
+    }
+
+    #[test]
+    fn test_edge_event_copy_outlives_buffer() {
+        EVENT_FREED.with(|c| c.set(0));
+        GPIOD_EDGE_EVENT_COPY_RESULT.with(|c| c.set(true));
 
-        // Call cleanup with None for all pointers.
-        cleanup(None, None, None, None);
+        let buffer = mock_buffer(vec![mock_edge(FALLING, 161_000)]);
+        let copy = buffer.event(0).unwrap().copy().unwrap();
+        drop(buffer);
+        MOCK_BUFFER_EVENTS.with(|e| e.borrow_mut().clear());
 
-        assert_eq!(CONFIG_FREED.load(Ordering::SeqCst), 0);
-        assert_eq!(SETTINGS_FREED.load(Ordering::SeqCst), 0);
-        assert_eq!(INFO_FREED.load(Ordering::SeqCst), 0);
-        assert_eq!(CHIP_FREED.load(Ordering::SeqCst), 0);
+        assert_eq!(copy.kind().unwrap(), EdgeKind::Falling);
+        assert_eq!(copy.timestamp(), Duration::from_micros(161));
+        assert_eq!(EVENT_FREED.with(Cell::get), 0);
+
+        drop(copy);
+        assert_eq!(EVENT_FREED.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn test_edge_event_copy_fails() {
+        GPIOD_EDGE_EVENT_COPY_RESULT.with(|c| c.set(false));
+        let buffer = mock_buffer(vec![mock_edge(RISING, 0)]);
+        let result = buffer.event(0).unwrap().copy();
+        assert!(matches!(result, Err(GpiodError::CopyEdgeEvent(_))));
     }
 }
@@ -0,0 +1,39 @@
+//! Reads DHT22 temperature and humidity sensors over the Linux GPIO character device, through
+//! libgpiod v2.
+//!
+//! [`gpiod`] is a safe wrapper around the parts of libgpiod the driver needs, with the raw
+//! bindings in [`sys`]. [`dht22`] speaks the sensor's protocol over anything implementing
+//! [`pin::SensorPin`], and [`retry`] asks again when a reading comes back garbled:
+//!
+//! ```no_run
+//! use std::ffi::CString;
+//! use dht22_reader::dht22::DEFAULT_TIMEOUT;
+//! use dht22_reader::gpiod::{Gpiod, IGpiod};
+//! use dht22_reader::model::SensorModel;
+//! use dht22_reader::pin::GpiodPin;
+//! use dht22_reader::retry::RetryPolicy;
+//!
+//! let gpiod = Gpiod {};
+//! let chip = gpiod.chip(&CString::new("/dev/gpiochip0")?)?;
+//! let mut pin = GpiodPin::new(&gpiod, &chip, 4)?;
+//! let reading = RetryPolicy::default()
+//!     .read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT)
+//!     .result?;
+//! println!("{reading}");
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod daemon;
+pub mod dht22;
+pub mod format;
+pub mod gpiod;
+pub mod lines;
+pub mod metrics;
+pub mod model;
+pub mod pin;
+pub mod retry;
+pub mod sim;
+pub mod sink;
+pub mod sys;
+pub mod trace;
+pub mod vcd;
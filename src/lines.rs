@@ -0,0 +1,352 @@
+use std::ffi::CString;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::gpiod::{
+    chip_paths, Bias, Chip, Direction, Drive, Edge, EventClock, GpiodError, IGpiod, InfoEvent,
+    InfoEventKind, LineInfo, Offset,
+};
+
+/// Every line on a chip, as reported by the kernel.
+#[derive(Debug, Serialize)]
+pub struct ChipLines {
+    pub path: PathBuf,
+    pub name: String,
+    pub label: String,
+    pub lines: Vec<Line>,
+}
+
+/// How a single line is set up, and who is using it.
+#[derive(Debug, Serialize)]
+pub struct Line {
+    pub offset: Offset,
+    pub name: Option<String>,
+    pub used: bool,
+    pub consumer: Option<String>,
+    pub direction: Direction,
+    pub bias: Bias,
+    pub drive: Drive,
+    pub edge_detection: Edge,
+    pub active_low: bool,
+    pub debounce_period_us: u64,
+    pub event_clock: EventClock,
+}
+
+impl Line {
+    pub fn from_info(info: &LineInfo) -> Result<Self, GpiodError> {
+        Ok(Line {
+            offset: info.offset(),
+            name: info.name(),
+            used: info.is_used(),
+            consumer: info.consumer(),
+            direction: info.direction()?,
+            bias: info.bias()?,
+            drive: info.drive()?,
+            edge_detection: info.edge_detection()?,
+            active_low: info.is_active_low(),
+            // The kernel reports the period in whole microseconds, so this can't truncate.
+            debounce_period_us: info.debounce_period().as_micros() as u64,
+            event_clock: info.event_clock()?,
+        })
+    }
+}
+
+/// Reads the info for every line on `chip`, which was opened from `path`.
+pub fn chip_lines(chip: &Chip, path: &Path) -> Result<ChipLines, GpiodError> {
+    let info = chip.info()?;
+    let lines = (0..info.num_lines())
+        .map(|offset| Line::from_info(&chip.line_info(offset as Offset)?))
+        .collect::<Result<_, _>>()?;
+
+    Ok(ChipLines {
+        path: path.to_path_buf(),
+        name: info.name()?,
+        label: info.label()?,
+        lines,
+    })
+}
+
+/// Reads the info for every line on every chip in `dir`. Chips that can't be opened (eg for lack
+/// of permissions), or whose info can't be read, are skipped so the rest are still listed.
+pub fn inventory(gpiod: &impl IGpiod, dir: &Path) -> Vec<ChipLines> {
+    let mut chips = vec![];
+    for path in chip_paths(dir) {
+        let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+            continue;
+        };
+        let Ok(chip) = gpiod.chip(&c_path) else {
+            continue;
+        };
+        if let Ok(lines) = chip_lines(&chip, &path) {
+            chips.push(lines);
+        }
+    }
+    chips
+}
+
+/// A change to a watched line.
+#[derive(Debug, Serialize)]
+pub struct LineEvent {
+    pub kind: InfoEventKind,
+    /// When the change happened, in nanoseconds on the monotonic clock.
+    pub timestamp_ns: u64,
+    pub line: Line,
+}
+
+impl LineEvent {
+    pub fn from_event(event: &InfoEvent) -> Result<Self, GpiodError> {
+        Ok(LineEvent {
+            kind: event.kind,
+            // The kernel reports timestamps as a u64, so this can't truncate.
+            timestamp_ns: event.timestamp.as_nanos() as u64,
+            line: Line::from_info(&event.line_info)?,
+        })
+    }
+}
+
+/// Writes an event on one line, in the style of `gpionotify`.
+pub fn write_event(out: &mut impl Write, event: &LineEvent) -> io::Result<()> {
+    let secs = event.timestamp_ns / 1_000_000_000;
+    let nanos = event.timestamp_ns % 1_000_000_000;
+    let line = &event.line;
+    write!(
+        out,
+        "{secs}.{nanos:09}: {} line {}",
+        event.kind, line.offset
+    )?;
+    if let Some(name) = &line.name {
+        write!(out, " ({name})")?;
+    }
+    if let Some(consumer) = &line.consumer {
+        write!(out, " by {consumer}")?;
+    }
+    writeln!(out)
+}
+
+const HEADERS: [&str; 11] = [
+    "OFFSET",
+    "NAME",
+    "USED",
+    "CONSUMER",
+    "DIRECTION",
+    "BIAS",
+    "DRIVE",
+    "EDGE",
+    "ACTIVE-LOW",
+    "DEBOUNCE",
+    "CLOCK",
+];
+
+/// Writes a table per chip, with columns sized to fit their contents.
+pub fn write_table(out: &mut impl Write, chips: &[ChipLines]) -> io::Result<()> {
+    for (i, chip) in chips.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(
+            out,
+            "{} [{}] ({} lines)",
+            chip.name,
+            chip.label,
+            chip.lines.len()
+        )?;
+
+        let rows: Vec<[String; 11]> = chip.lines.iter().map(row).collect();
+        let mut widths = HEADERS.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        write_row(out, &HEADERS, &widths)?;
+        for row in &rows {
+            write_row(out, row, &widths)?;
+        }
+    }
+    Ok(())
+}
+
+fn row(line: &Line) -> [String; 11] {
+    let yes_no = |b: bool| if b { "yes" } else { "no" }.to_string();
+    let debounce = match line.debounce_period_us {
+        0 => "-".to_string(),
+        us => format!("{us}us"),
+    };
+    [
+        line.offset.to_string(),
+        line.name.clone().unwrap_or_else(|| "-".to_string()),
+        yes_no(line.used),
+        line.consumer.clone().unwrap_or_else(|| "-".to_string()),
+        line.direction.to_string(),
+        line.bias.to_string(),
+        line.drive.to_string(),
+        line.edge_detection.to_string(),
+        yes_no(line.active_low),
+        debounce,
+        line.event_clock.to_string(),
+    ]
+}
+
+fn write_row(out: &mut impl Write, cells: &[impl AsRef<str>], widths: &[usize]) -> io::Result<()> {
+    let mut line = String::new();
+    for (i, (cell, width)) in cells.iter().zip(widths).enumerate() {
+        let cell = cell.as_ref();
+        // Offsets line up on the right, everything else on the left.
+        if i == 0 {
+            line.push_str(&format!("{cell:>width$}"));
+        } else {
+            line.push_str(&format!("  {cell:<width$}"));
+        }
+    }
+    writeln!(out, "  {}", line.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpiod::Gpiod;
+
+    fn chips() -> Vec<ChipLines> {
+        vec![ChipLines {
+            path: PathBuf::from("/dev/gpiochip0"),
+            name: "gpiochip0".to_string(),
+            label: "pinctrl-rp1".to_string(),
+            lines: vec![
+                Line {
+                    offset: 4,
+                    name: Some("GPIO4".to_string()),
+                    used: true,
+                    consumer: Some("dht22_reader".to_string()),
+                    direction: Direction::Input,
+                    bias: Bias::Disabled,
+                    drive: Drive::OpenDrain,
+                    edge_detection: Edge::Both,
+                    active_low: false,
+                    debounce_period_us: 0,
+                    event_clock: EventClock::Monotonic,
+                },
+                Line {
+                    offset: 17,
+                    name: None,
+                    used: false,
+                    consumer: None,
+                    direction: Direction::Output,
+                    bias: Bias::PullUp,
+                    drive: Drive::PushPull,
+                    edge_detection: Edge::None,
+                    active_low: true,
+                    debounce_period_us: 250,
+                    event_clock: EventClock::Realtime,
+                },
+            ],
+        }]
+    }
+
+    #[test]
+    fn test_write_table() {
+        let mut out = vec![];
+        write_table(&mut out, &chips()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "gpiochip0 [pinctrl-rp1] (2 lines)\n\
+             \x20 OFFSET  NAME   USED  CONSUMER      DIRECTION  BIAS      DRIVE       EDGE  ACTIVE-LOW  DEBOUNCE  CLOCK\n\
+             \x20      4  GPIO4  yes   dht22_reader  input      disabled  open-drain  both  no          -         monotonic\n\
+             \x20     17  -      no    -             output     pull-up   push-pull   none  yes         250us     realtime\n"
+        );
+    }
+
+    #[test]
+    fn test_write_table_separates_chips() {
+        let mut chips = chips();
+        chips.push(ChipLines {
+            path: PathBuf::from("/dev/gpiochip1"),
+            name: "gpiochip1".to_string(),
+            label: "empty".to_string(),
+            lines: vec![],
+        });
+        let mut out = vec![];
+        write_table(&mut out, &chips).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("realtime\n\ngpiochip1 [empty] (0 lines)\n  OFFSET"));
+    }
+
+    #[test]
+    fn test_write_event() {
+        let mut lines = chips().remove(0).lines.into_iter();
+        let mut out = vec![];
+        write_event(
+            &mut out,
+            &LineEvent {
+                kind: InfoEventKind::Requested,
+                timestamp_ns: 12_000_000_345,
+                line: lines.next().unwrap(),
+            },
+        )
+        .unwrap();
+        write_event(
+            &mut out,
+            &LineEvent {
+                kind: InfoEventKind::Released,
+                timestamp_ns: 13_500_000_000,
+                line: lines.next().unwrap(),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "12.000000345: requested line 4 (GPIO4) by dht22_reader\n\
+             13.500000000: released line 17\n"
+        );
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = LineEvent {
+            kind: InfoEventKind::ConfigChanged,
+            timestamp_ns: 42,
+            line: chips().remove(0).lines.remove(0),
+        };
+        let json = serde_json::to_value(event).unwrap();
+        assert_eq!(json["kind"], "config-changed");
+        assert_eq!(json["timestamp_ns"], 42);
+        assert_eq!(json["line"]["consumer"], "dht22_reader");
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::to_value(chips()).unwrap();
+        assert_eq!(json[0]["path"], "/dev/gpiochip0");
+        assert_eq!(json[0]["label"], "pinctrl-rp1");
+
+        let line = &json[0]["lines"][0];
+        assert_eq!(line["offset"], 4);
+        assert_eq!(line["name"], "GPIO4");
+        assert_eq!(line["used"], true);
+        assert_eq!(line["consumer"], "dht22_reader");
+        assert_eq!(line["direction"], "input");
+        assert_eq!(line["drive"], "open-drain");
+        assert_eq!(line["edge_detection"], "both");
+        assert_eq!(line["event_clock"], "monotonic");
+
+        let line = &json[0]["lines"][1];
+        assert_eq!(line["name"], serde_json::Value::Null);
+        assert_eq!(line["bias"], "pull-up");
+        assert_eq!(line["debounce_period_us"], 250);
+    }
+
+    #[test]
+    fn test_inventory_skips_chip_without_info() {
+        let dir =
+            std::env::temp_dir().join(format!("dht22_reader_{}_inventory", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("gpiochip-noinfo"), b"").unwrap();
+
+        assert!(inventory(&Gpiod {}, &dir).is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
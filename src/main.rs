@@ -1,213 +1,386 @@
-#![allow(improper_ctypes)]
-#![allow(non_upper_case_globals)]
-#![allow(non_camel_case_types)]
-#![allow(non_snake_case)]
-#![allow(dead_code)]
-// I'm checking for null ptr derefs already
-#![allow(clippy::not_unsafe_ptr_arg_deref)]
+mod cli;
 
-include!("bindings/bindings.rs");
+use clap::{error::ErrorKind, CommandFactory, Parser};
+use cli::{Cli, Command, Failure, LineId, ModelChoice};
+use dht22_reader::daemon::{self, Event};
+use dht22_reader::format::Sensor;
+use dht22_reader::gpiod::{Chip, Gpiod, GpiodError, IGpiod, Offset, DEV_DIR};
+use dht22_reader::metrics::{self, Metrics};
+use dht22_reader::model::{self, MismatchCheck, SensorModel};
+use dht22_reader::pin::{GpiodPin, SensorPin};
+use dht22_reader::sink::{FormattedSink, Sample, Sink, SinkSpec};
+use dht22_reader::trace::{RecordingPin, TraceError, TraceReader, TraceWriter};
+use dht22_reader::vcd::VcdWriter;
+use dht22_reader::{dht22, lines, trace};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::Signals,
+};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    net::SocketAddr,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    process::ExitCode,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
 
-// FIXME: These should ultimately be configurable, but will hardcode them for my board setup for
-// now.
-// Chip: A chip with pins on it. RPI's just have the 1, which will be at index 0
-const GPIO_CHIP_PATH: &str = "/dev/gpiochip0";
-const TIMEOUT: u128 = 1000;
+// How many frames `--model auto` takes to decide which model is attached.
+const DETECTION_READS: usize = 3;
 
-mod gpiod;
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-use gpiod::{cleanup, Gpiod, GpiodError, IGpiod, OFFSET};
-use std::{ffi::CString, time::Instant};
+    let result = match cli.command {
+        Command::Read {
+            count,
+            interval,
+            ref trace,
+            ref vcd,
+        } => read(&cli, count, interval, trace.as_deref(), vcd.as_deref()),
+        Command::Daemon {
+            interval,
+            ref sinks,
+            metrics,
+            ref name,
+        } => daemon(&cli, interval, sinks, metrics, name.as_deref()).map(|_| ExitCode::SUCCESS),
+        Command::Replay { ref trace } => replay(trace, cli.model),
+        Command::Lines { json } => list_lines(&cli, json).map(|_| ExitCode::SUCCESS),
+        Command::WatchLines { json } => watch_lines(&cli, json).map(|_| ExitCode::SUCCESS),
+    };
 
-fn main() {
-    let path = CString::new(GPIO_CHIP_PATH).expect("CString::new failed");
-    let path_ptr = path.as_ptr();
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        ExitCode::from(e.exit_code())
+    })
+}
 
+/// Takes `count` readings `interval` apart, recording the edges behind each one to `trace` and
+/// the line's waveform to `vcd` if given. A failed reading is printed but doesn't stop the rest
+/// from being taken; the exit code reflects the last one that failed.
+fn read(
+    cli: &Cli,
+    count: u32,
+    interval: Duration,
+    trace: Option<&Path>,
+    vcd: Option<&Path>,
+) -> Result<ExitCode, Failure> {
     let gpiod = Gpiod {};
+    let (chip, offset) = locate_line(&gpiod, cli)?;
 
-    let chip = match gpiod.chip(path_ptr) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Error creating chip: {}", e);
-            return;
+    let mut writer = match trace {
+        Some(path) => Some(
+            File::create(path)
+                .and_then(|file| TraceWriter::new(BufWriter::new(file)))
+                .map_err(|e| trace_failure(path, e.into()))?,
+        ),
+        None => None,
+    };
+    let mut waveform = match vcd {
+        Some(path) => {
+            let name = match &cli.line {
+                LineId::Name(name) => name.clone(),
+                LineId::Offset(offset) => format!("line{offset}"),
+            };
+            Some(
+                File::create(path)
+                    .and_then(|file| VcdWriter::new(BufWriter::new(file), &name))
+                    .map_err(|e| waveform_failure(path, e))?,
+            )
         }
+        None => None,
     };
 
-    let info = match gpiod.info(chip) {
-        Ok(i) => i,
-        Err(e) => {
-            eprintln!("Error obtaining chip info: {}", e);
-            cleanup(Some(chip), None, None, None);
-            return;
+    let pin = GpiodPin::new(&gpiod, &chip, offset).map_err(Failure::Request)?;
+    let mut pin = RecordingPin::new(pin);
+    let model = resolve_model(cli, &mut pin)?;
+    let sensor = sensor(&chip, offset, model)?;
+    let mut out = FormattedSink::new(io::stdout().lock(), cli.formatter(sensor));
+
+    let policy = cli.retry_policy(model);
+    // Wiring up the wrong model can give readings that are plausible but wrong, so say so.
+    let mut check = MismatchCheck::new(model);
+    let mut code = ExitCode::SUCCESS;
+    for i in 0..count {
+        if i > 0 {
+            thread::sleep(interval);
         }
-    };
 
-    let name = match gpiod.name(info) {
-        Ok(n) => n,
-        Err(e) => {
-            eprintln!("Error obtaining chip name: {}", e);
-            cleanup(Some(chip), Some(info), None, None);
-            return;
+        // Every attempt is recorded, as the failed ones are what traces are for.
+        let mut recorded = Ok(());
+        let outcome = policy.retry(
+            || {
+                let result = dht22::read_frame(&mut pin, model.start_signal(), cli.timeout());
+                if recorded.is_ok() {
+                    recorded = record(&pin, writer.as_mut().zip(trace), waveform.as_mut().zip(vcd));
+                }
+                if let Some(suggested) = result.as_ref().ok().and_then(|f| check.observe(f)) {
+                    cli::warn_model_mismatch(model, suggested);
+                }
+                result.map(|frame| model.decode(&frame))
+            },
+            thread::sleep,
+        );
+        recorded?;
+
+        match outcome.result {
+            Ok(reading) => out.publish(&Sample::now(reading, outcome.attempts))?,
+            Err(e) => {
+                let failure = Failure::from(e);
+                failure.report(outcome.attempts);
+                code = ExitCode::from(failure.exit_code());
+            }
         }
-    };
-    println!("{}", name);
-
-    let settings = match gpiod.settings() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error creating new settings object: {}", e);
-            cleanup(Some(chip), Some(info), None, None);
-            return;
+    }
+
+    Ok(code)
+}
+
+/// Works out which model to read the sensor on `pin` as. A model given on the command line is
+/// taken at its word, where `auto` takes a few frames to decide, falling back to the default if
+/// they don't settle it. Detection waits out the sensor's cooldown after its last frame, so the
+/// first reading can be taken straight after.
+fn resolve_model(cli: &Cli, pin: &mut impl SensorPin) -> Result<SensorModel, Failure> {
+    let ModelChoice::Known(model) = cli.model else {
+        let detected = model::detect(pin, DETECTION_READS, cli.timeout(), thread::sleep)?;
+        let model = detected.unwrap_or_default();
+        match detected {
+            Some(SensorModel::Dht11) => eprintln!("Detected a dht11"),
+            Some(_) => eprintln!("Detected a dht22, or one of the sensors that send its format"),
+            None => eprintln!("Warning: couldn't tell which model is attached, assuming {model}"),
         }
+        return Ok(model);
     };
+    Ok(model)
+}
 
-    // The DHT22 protocol initiate reading data by setting the pin to a pull up bias. Then, we pull
-    // low for between 1~10ms. We then pull up for 20-40us (will let the bias take care of that)
-    // and await a response from the sensor.
-    match gpiod.settings_set_direction(settings, gpiod_line_direction_GPIOD_LINE_DIRECTION_OUTPUT) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error setting direction: {}", e);
-            cleanup(Some(chip), Some(info), Some(settings), None);
-            return;
-        }
+/// Describes the `model` sensor on `offset` of `chip`, for the formats that label readings.
+fn sensor(chip: &Chip, offset: Offset, model: SensorModel) -> Result<Sensor, Failure> {
+    Ok(Sensor {
+        chip: chip.path().map_err(Failure::Chip)?,
+        offset,
+        model,
+    })
+}
+
+/// Writes what `pin` saw during its last read to a trace and a waveform, either of which may be
+/// missing.
+fn record(
+    pin: &RecordingPin<GpiodPin>,
+    trace: Option<(&mut TraceWriter<BufWriter<File>>, &Path)>,
+    vcd: Option<(&mut VcdWriter<BufWriter<File>>, &Path)>,
+) -> Result<(), Failure> {
+    if let Some((writer, path)) = trace {
+        writer
+            .write_capture(pin.edges())
+            .map_err(|e| trace_failure(path, e.into()))?;
     }
-    match gpiod.settings_set_bias(settings, gpiod_line_bias_GPIOD_LINE_BIAS_DISABLED) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error setting bias: {}", e);
-            cleanup(Some(chip), Some(info), Some(settings), None);
-            return;
-        }
+    if let Some((waveform, path)) = vcd {
+        waveform
+            .write_transitions(&pin.transitions())
+            .map_err(|e| waveform_failure(path, e))?;
     }
+    Ok(())
+}
 
-    match gpiod.settings_set_drive(settings, gpiod_line_drive_GPIOD_LINE_DRIVE_OPEN_DRAIN) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error setting drive: {}", e);
-            cleanup(Some(chip), Some(info), Some(settings), None);
-            return;
-        }
+/// Takes readings until SIGTERM or SIGINT, then releases the line and exits cleanly. Metrics are
+/// served on `metrics_addr` if given, labelled with `name` or failing that the line.
+fn daemon(
+    cli: &Cli,
+    interval: Duration,
+    sinks: &[SinkSpec],
+    metrics_addr: Option<SocketAddr>,
+    name: Option<&str>,
+) -> Result<(), Failure> {
+    // An `auto` model isn't known until the line is requested, so the interval is checked against
+    // the slowest model.
+    let min_interval = cli.model.min_interval();
+    if interval < min_interval {
+        Cli::command()
+            .error(
+                ErrorKind::ValueValidation,
+                format!(
+                    "--interval must be at least {}s for the {}, or it can't keep up",
+                    min_interval.as_secs(),
+                    cli.model
+                ),
+            )
+            .exit();
     }
 
-    let config = match gpiod.config() {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Error creating new config object: {}", e);
-            cleanup(Some(chip), Some(info), Some(settings), None);
-            return;
+    let (stop, stopped) = mpsc::channel();
+    let mut signals = Signals::new([SIGTERM, SIGINT]).map_err(Failure::Signals)?;
+    thread::spawn(move || {
+        if signals.forever().next().is_some() {
+            let _ = stop.send(());
         }
-    };
+    });
 
-    match gpiod.config_add_settings(config, settings) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error adding settings to config: {}", e);
-            cleanup(Some(chip), Some(info), Some(settings), Some(config));
-            return;
-        }
-    }
+    let gpiod = Gpiod {};
+    let (chip, offset) = locate_line(&gpiod, cli)?;
+    let mut pin = GpiodPin::new(&gpiod, &chip, offset).map_err(Failure::Request)?;
+    let model = resolve_model(cli, &mut pin)?;
 
-    // Wait 1ms before pulling low
-    std::thread::sleep(std::time::Duration::from_millis(1));
+    // Sinks wait for the model, as stdout labels readings with it.
+    let sensor = sensor(&chip, offset, model)?;
+    let formatter = cli.formatter(sensor.clone());
+    let mut sinks = sinks
+        .iter()
+        .map(|spec| {
+            spec.open(&formatter)
+                .map_err(|e| Failure::Sink(spec.to_string(), e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let request = match gpiod.chip_request_lines(chip, config) {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("Error requesting line: {}", e);
-            cleanup(Some(chip), Some(info), Some(settings), Some(config));
-            return;
-        }
+    let metrics = Metrics::new(&sensor, name.unwrap_or(&cli.line.to_string()));
+    if let Some(addr) = metrics_addr {
+        let addr = metrics::serve(metrics.clone(), addr).map_err(|e| Failure::Metrics(addr, e))?;
+        eprintln!("Serving metrics on http://{addr}/metrics");
+    }
+
+    let settings = daemon::Settings {
+        model,
+        policy: cli.retry_policy(model),
+        interval,
+        timeout: cli.timeout(),
     };
+    daemon::run(
+        &mut pin,
+        &settings,
+        &mut sinks,
+        &metrics,
+        &stopped,
+        |event| match event {
+            Event::ModelMismatch { suggested } => cli::warn_model_mismatch(model, suggested),
+            Event::ReadingFailed { error, attempts } => Failure::from(error).report(attempts),
+        },
+    )?;
+    Ok(())
+}
+
+/// Decodes every capture in a trace, as if the readings were being taken again. As with `read`,
+/// the exit code reflects the last reading that failed.
+fn replay(path: &Path, model: ModelChoice) -> Result<ExitCode, Failure> {
+    let file = File::open(path).map_err(|e| trace_failure(path, e.into()))?;
+    let reader = TraceReader::new(BufReader::new(file)).map_err(|e| trace_failure(path, e))?;
 
-    match gpiod.line_request_set_value(request, OFFSET, 0) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error pulling line low: {}", e);
-            cleanup(Some(chip), Some(info), None, None);
-            return;
+    let mut code = ExitCode::SUCCESS;
+    for (i, edges) in reader.enumerate() {
+        let edges = edges.map_err(|e| trace_failure(path, e))?;
+        let mut summary = format!("Capture {}: {} edges", i + 1, edges.len());
+        let dropped = trace::dropped_edges(&edges);
+        if dropped > 0 {
+            summary.push_str(&format!(" ({dropped} dropped)"));
         }
-    }
-    std::thread::sleep(std::time::Duration::from_millis(1));
-
-    // Pull high for 40us
-    match gpiod.line_request_set_value(request, OFFSET, 1) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error setting line value: {}", e);
-            cleanup(Some(chip), Some(info), None, None);
-            return;
+        // With `auto`, each capture is decoded as whichever model it looks like.
+        let result = dht22::captured_frame(&edges).map(|frame| match model {
+            ModelChoice::Known(model) => model.decode(&frame),
+            ModelChoice::Auto => model::guess(&frame).unwrap_or_default().decode(&frame),
+        });
+        match result {
+            Ok(reading) => println!("{summary}: {reading}"),
+            Err(e) => {
+                let failure = Failure::from(e);
+                eprintln!("{summary}: {failure}");
+                code = ExitCode::from(failure.exit_code());
+            }
         }
     }
-    std::thread::sleep(std::time::Duration::from_micros(40));
-
-    // Now reconfigure the line to read and wait input from the DHT22 sensor.
-    // New settings object for the same line
-    let new_settings = match gpiod.settings() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error creating new settings object: {}", e);
-            cleanup(Some(chip), Some(info), None, None);
-            return;
-        }
-    };
-    gpiod
-        .settings_set_direction(
-            new_settings,
-            gpiod_line_direction_GPIOD_LINE_DIRECTION_INPUT,
-        )
-        .unwrap();
-
-    // Create config using the settings object
-    let new_config = match gpiod.config() {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Error creating new config object: {}", e);
-            return;
-        }
-    };
 
-    match gpiod.config_add_settings(new_config, new_settings) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error adding settings to new config: {}", e);
-            cleanup(Some(chip), Some(info), Some(new_settings), Some(new_config));
-            return;
-        }
-    };
+    Ok(code)
+}
 
-    match gpiod.line_request_reconfigure_lines(request, new_config) {
-        Ok(_) => (),
-        Err(e) => {
-            eprintln!("Error reconfiguring line: {}", e);
-            return;
-        }
-    }
+fn trace_failure(path: &Path, e: TraceError) -> Failure {
+    Failure::Trace(path.display().to_string(), e)
+}
 
-    // Once we have the request object, we can clean the rest up.
-    cleanup(Some(chip), Some(info), Some(settings), Some(config));
+fn waveform_failure(path: &Path, e: io::Error) -> Failure {
+    Failure::Waveform(path.display().to_string(), e)
+}
 
-    // Now we expect the sensor to pull low for 80us, then high for 80us as an ack:
-    let pulse = expect_pulse(false, request).unwrap();
-    println!("Pulse low: {}us", pulse);
-    let pulse = expect_pulse(true, request).unwrap();
-    println!("Pulse high: {}us", pulse);
+/// Prints the lines of the chip given with `--chip`, or of every chip if none was.
+fn list_lines(cli: &Cli, json: bool) -> Result<(), Failure> {
+    let gpiod = Gpiod {};
+    let chips = match cli.chip_path() {
+        Some(path) => {
+            let chip = open_chip(&gpiod, &path)?;
+            vec![lines::chip_lines(&chip, &path).map_err(Failure::LineInfo)?]
+        }
+        None => lines::inventory(&gpiod, Path::new(DEV_DIR)),
+    };
 
-    println!("Printing pulses in a loop");
-    for n in 1..100 {
-        let pulse = expect_pulse(true, request).unwrap();
-        println!("{}: Pulse high: {}us", n, pulse);
+    let mut out = io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut out, &chips).map_err(io::Error::from)?;
+        writeln!(out)?;
+    } else {
+        lines::write_table(&mut out, &chips)?;
     }
+    Ok(())
 }
 
-fn expect_pulse(value: bool, request: *mut gpiod::gpiod_line_request) -> Result<u128, GpiodError> {
-    let start = Instant::now();
+/// Prints changes to the sensor's line until interrupted.
+fn watch_lines(cli: &Cli, json: bool) -> Result<(), Failure> {
+    let gpiod = Gpiod {};
+    let (chip, offset) = locate_line(&gpiod, cli)?;
+    chip.watch_line_info(offset).map_err(Failure::LineInfo)?;
 
-    while (Gpiod {}.line_request_get_value(request, OFFSET).unwrap() == value) {
-        if start.elapsed().as_micros() > TIMEOUT {
-            return Err(GpiodError::Timeout);
+    let mut out = io::stdout().lock();
+    loop {
+        let event = chip
+            .read_info_event()
+            .and_then(|e| lines::LineEvent::from_event(&e))
+            .map_err(Failure::LineInfo)?;
+        if json {
+            serde_json::to_writer(&mut out, &event).map_err(io::Error::from)?;
+            writeln!(out)?;
+        } else {
+            lines::write_event(&mut out, &event)?;
         }
+        // Whoever is watching wants to know as soon as it happens, even through a pipe.
+        out.flush()?;
     }
+}
+
+/// Opens the chip the sensor's line is on and works out the line's offset on it, searching every
+/// chip for the line if no chip was given.
+fn locate_line(gpiod: &Gpiod, cli: &Cli) -> Result<(Chip, Offset), Failure> {
+    let path = match (cli.chip_path(), &cli.line) {
+        (Some(path), _) => path,
+        (None, LineId::Name(name)) => {
+            let (path, offset) = gpiod
+                .find_line(Path::new(DEV_DIR), name)
+                .map_err(|e| Failure::Line(name.clone(), e))?;
+            return Ok((open_chip(gpiod, &path)?, offset));
+        }
+        (None, LineId::Offset(_)) => Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--chip is required when --line is an offset",
+            )
+            .exit(),
+    };
+
+    let chip = open_chip(gpiod, &path)?;
+    let offset = match &cli.line {
+        LineId::Offset(offset) => *offset,
+        LineId::Name(name) => chip
+            .line_offset_from_name(name)
+            .map_err(|e| Failure::Line(name.clone(), e))?,
+    };
+    Ok((chip, offset))
+}
 
-    Ok(start.elapsed().as_micros())
+fn open_chip(gpiod: &Gpiod, path: &Path) -> Result<Chip, Failure> {
+    let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|_| {
+        Failure::Chip(GpiodError::OpenChip {
+            path: path.to_path_buf(),
+            source: io::ErrorKind::InvalidInput.into(),
+        })
+    })?;
+    gpiod.chip(&c_path).map_err(Failure::Chip)
 }
@@ -0,0 +1,273 @@
+//! Prometheus metrics for a sensor read by the daemon, and a server for them to be scraped from.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+
+use prometheus::{Encoder, Gauge, Histogram, HistogramOpts, IntCounter, Registry, TextEncoder};
+use tiny_http::{Header, Method, Response, Server};
+
+use crate::dht22::{Dht22Error, Reading};
+use crate::format::Sensor;
+use crate::retry::Outcome;
+
+// Reads take around 5ms when they work, and up to a few timeouts when they don't.
+const LATENCY_BUCKETS: [f64; 8] = [0.001, 0.002, 0.005, 0.01, 0.02, 0.05, 0.1, 0.25];
+
+/// The metrics for one sensor, labelled with its chip, line and a name to tell it apart by.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    temperature: Gauge,
+    humidity: Gauge,
+    reads: IntCounter,
+    checksum_failures: IntCounter,
+    timeouts: IntCounter,
+    retries: IntCounter,
+    latency: Histogram,
+}
+
+impl Metrics {
+    pub fn new(sensor: &Sensor, name: &str) -> Self {
+        let labels = HashMap::from([
+            ("chip".to_string(), sensor.chip.clone()),
+            ("line".to_string(), sensor.offset.to_string()),
+            ("name".to_string(), name.to_string()),
+        ]);
+        // Everything below is fixed, so only a typo in it could make registration fail.
+        let registry = Registry::new_custom(Some("dht22".to_string()), Some(labels))
+            .expect("metric labels are valid");
+        let register = |collector: Box<dyn prometheus::core::Collector>| {
+            registry
+                .register(collector)
+                .expect("metrics are registered once each")
+        };
+
+        let temperature = Gauge::new("temperature_celsius", "Last temperature read").unwrap();
+        let humidity = Gauge::new("humidity_percent", "Last relative humidity read").unwrap();
+        let reads = IntCounter::new("reads_total", "Readings taken successfully").unwrap();
+        let checksum_failures = IntCounter::new(
+            "checksum_failures_total",
+            "Attempts that failed their checksum",
+        )
+        .unwrap();
+        let timeouts = IntCounter::new(
+            "timeouts_total",
+            "Attempts where the sensor didn't respond, or stopped mid frame",
+        )
+        .unwrap();
+        let retries = IntCounter::new("retries_total", "Attempts after a reading's first").unwrap();
+        let latency = Histogram::with_opts(
+            HistogramOpts::new("read_duration_seconds", "How long each attempt took")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+        )
+        .unwrap();
+
+        register(Box::new(temperature.clone()));
+        register(Box::new(humidity.clone()));
+        register(Box::new(reads.clone()));
+        register(Box::new(checksum_failures.clone()));
+        register(Box::new(timeouts.clone()));
+        register(Box::new(retries.clone()));
+        register(Box::new(latency.clone()));
+
+        Metrics {
+            registry,
+            temperature,
+            humidity,
+            reads,
+            checksum_failures,
+            timeouts,
+            retries,
+            latency,
+        }
+    }
+
+    /// Records an attempt at a reading that took `latency`. A sensor that doesn't respond in time
+    /// sends a short frame, so those are what count as timeouts.
+    pub fn observe_attempt(&self, result: &Result<Reading, Dht22Error>, latency: Duration) {
+        self.latency.observe(latency.as_secs_f64());
+        match result {
+            Err(Dht22Error::Checksum { .. }) => self.checksum_failures.inc(),
+            Err(Dht22Error::ShortFrame(_)) => self.timeouts.inc(),
+            Ok(_) | Err(Dht22Error::Gpiod(_)) => {}
+        }
+    }
+
+    /// Records the outcome of a reading, once its attempts are over.
+    pub fn observe_reading(&self, outcome: &Outcome<Reading>) {
+        self.retries
+            .inc_by(u64::from(outcome.attempts.saturating_sub(1)));
+        if let Ok(reading) = &outcome.result {
+            self.reads.inc();
+            self.temperature.set(reading.temperature_celsius.into());
+            self.humidity.set(reading.humidity_percent.into());
+        }
+    }
+
+    /// Returns the metrics in Prometheus' text format.
+    pub fn encode(&self) -> String {
+        let mut out = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut out)
+            .expect("metrics encode to a Vec");
+        String::from_utf8(out).expect("metrics are UTF-8")
+    }
+}
+
+/// Serves `metrics` at `/metrics` on `addr` from a thread of its own, for as long as the process
+/// runs. Returns the address it's listening on, which says which port was picked if `addr`'s was
+/// 0.
+pub fn serve(metrics: Metrics, addr: SocketAddr) -> io::Result<SocketAddr> {
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .expect("servers bound to a socket address listen on one");
+    let content_type = Header::from_bytes("Content-Type", TextEncoder::new().format_type())
+        .expect("the content type is a valid header");
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match (request.method(), request.url()) {
+                (Method::Get, "/metrics") => {
+                    Response::from_string(metrics.encode()).with_header(content_type.clone())
+                }
+                _ => Response::from_string("Not found").with_status_code(404),
+            };
+            // A scraper that hangs up early will try again, so there's nobody to tell.
+            let _ = request.respond(response);
+        }
+    });
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SensorModel;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Instant;
+
+    const READING: Reading = Reading {
+        humidity_percent: 65.5,
+        temperature_celsius: -10.25,
+    };
+
+    fn metrics() -> Metrics {
+        let sensor = Sensor {
+            chip: "/dev/gpiochip0".to_string(),
+            offset: 4,
+            model: SensorModel::Dht22,
+        };
+        Metrics::new(&sensor, "attic")
+    }
+
+    // Whether `text` has `metric`, labelled as the sensor in `metrics()`, with `value`. Labels
+    // come out in no particular order.
+    fn has_sample(text: &str, metric: &str, value: &str) -> bool {
+        let (name, extra) = match metric.split_once('{') {
+            Some((name, extra)) => (name, extra.strip_suffix('}')),
+            None => (metric, None),
+        };
+        let mut expected = vec![r#"chip="/dev/gpiochip0""#, r#"line="4""#, r#"name="attic""#];
+        expected.extend(extra);
+        expected.sort();
+        text.lines().any(|line| {
+            let Some((labels, v)) = line
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('{'))
+                .and_then(|rest| rest.split_once("} "))
+            else {
+                return false;
+            };
+            let mut labels: Vec<_> = labels.split(',').collect();
+            labels.sort();
+            labels == expected && v == value
+        })
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_observe() {
+        let metrics = metrics();
+        metrics.observe_attempt(
+            &Err(Dht22Error::Checksum {
+                expected: 1,
+                actual: 2,
+            }),
+            Duration::from_millis(5),
+        );
+        metrics.observe_attempt(&Err(Dht22Error::ShortFrame(0)), Duration::from_millis(30));
+        metrics.observe_attempt(&Ok(READING), Duration::from_millis(5));
+        metrics.observe_reading(&Outcome {
+            result: Ok(READING),
+            attempts: 3,
+            last_attempt: Instant::now(),
+        });
+
+        let text = metrics.encode();
+        for (metric, value) in [
+            ("dht22_temperature_celsius", "-10.25"),
+            ("dht22_humidity_percent", "65.5"),
+            ("dht22_reads_total", "1"),
+            ("dht22_checksum_failures_total", "1"),
+            ("dht22_timeouts_total", "1"),
+            ("dht22_retries_total", "2"),
+            ("dht22_read_duration_seconds_bucket{le=\"0.005\"}", "2"),
+            ("dht22_read_duration_seconds_count", "3"),
+        ] {
+            assert!(
+                has_sample(&text, metric, value),
+                "no {metric} {value} in\n{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_failed_reading_keeps_last_values() {
+        let metrics = metrics();
+        metrics.observe_reading(&Outcome {
+            result: Ok(READING),
+            attempts: 1,
+            last_attempt: Instant::now(),
+        });
+        metrics.observe_reading(&Outcome {
+            result: Err(Dht22Error::ShortFrame(0)),
+            attempts: 3,
+            last_attempt: Instant::now(),
+        });
+        let text = metrics.encode();
+        assert!(has_sample(&text, "dht22_reads_total", "1"));
+        assert!(has_sample(&text, "dht22_temperature_celsius", "-10.25"));
+        assert!(has_sample(&text, "dht22_retries_total", "2"));
+    }
+
+    #[test]
+    fn test_serve() {
+        let metrics = metrics();
+        metrics.observe_attempt(&Ok(READING), Duration::from_millis(5));
+        let addr = serve(metrics, "127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains("text/plain; version=0.0.4"));
+        assert!(response.contains("dht22_read_duration_seconds_count{"));
+
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,419 @@
+//! The sensors that speak the DHT22's single-wire protocol, and where they differ from it.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::dht22::{self, Dht22Error, Reading};
+use crate::pin::SensorPin;
+
+/// A model of sensor in the DHT family. They all answer the same start signal with the same 40 bit
+/// frame, but differ in how long they need to be woken, how often they can be asked, and how the
+/// frame's bytes encode the values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorModel {
+    Dht11,
+    /// Also sold as the AM2301.
+    Dht21,
+    #[default]
+    Dht22,
+    /// A DHT22 with its pull-up resistor built in.
+    Am2302,
+    /// SparkFun's name for the DHT22.
+    Rht03,
+}
+
+impl SensorModel {
+    pub const ALL: [SensorModel; 5] = [
+        SensorModel::Dht11,
+        SensorModel::Dht21,
+        SensorModel::Dht22,
+        SensorModel::Am2302,
+        SensorModel::Rht03,
+    ];
+
+    /// How long the host holds the line low to ask for a reading. The DHT11 ignores anything
+    /// shorter than 18ms, where the rest ask for at least 1ms.
+    pub fn start_signal(self) -> Duration {
+        match self {
+            SensorModel::Dht11 => Duration::from_millis(18),
+            _ => Duration::from_millis(1),
+        }
+    }
+
+    /// How long the sensor needs between readings. Asking sooner gets a stale or garbled frame.
+    pub fn min_interval(self) -> Duration {
+        match self {
+            SensorModel::Dht11 => Duration::from_secs(1),
+            _ => Duration::from_secs(2),
+        }
+    }
+
+    /// Whether `other` sends its frames in the same format, so a frame from one decodes the same as
+    /// the other.
+    pub fn shares_format_with(self, other: SensorModel) -> bool {
+        (self == SensorModel::Dht11) == (other == SensorModel::Dht11)
+    }
+
+    /// Converts a checksum-validated frame into a reading.
+    ///
+    /// The DHT11 sends each value as an integer byte followed by a byte of tenths, with the top
+    /// bit of the temperature's tenths marking it negative. The rest send 16 bit tenths, as
+    /// [`Reading::from_frame`] decodes.
+    pub fn decode(self, frame: &[u8; 5]) -> Reading {
+        match self {
+            SensorModel::Dht11 => {
+                let tenths = |integer: u8, tenths: u8| u16::from(integer) * 10 + u16::from(tenths);
+                let humidity = tenths(frame[0], frame[1]);
+                let temperature = tenths(frame[2], frame[3] & 0x7F);
+                let sign = if frame[3] & 0x80 != 0 { -1.0 } else { 1.0 };
+                Reading {
+                    humidity_percent: f32::from(humidity) / 10.0,
+                    temperature_celsius: sign * f32::from(temperature) / 10.0,
+                }
+            }
+            _ => Reading::from_frame(frame),
+        }
+    }
+
+    /// Encodes `reading` as this model would send it, rounded to the nearest tenth and with a
+    /// valid checksum. The inverse of [`SensorModel::decode`].
+    pub fn encode(self, reading: Reading) -> [u8; 5] {
+        match self {
+            SensorModel::Dht11 => {
+                // Tenths fit a u16 for anything the sensor can measure.
+                let humidity = (reading.humidity_percent * 10.0).round() as u16;
+                let temperature = (reading.temperature_celsius.abs() * 10.0).round() as u16;
+                let sign = if reading.temperature_celsius < 0.0 {
+                    0x80
+                } else {
+                    0
+                };
+                let mut frame = [
+                    (humidity / 10) as u8,
+                    (humidity % 10) as u8,
+                    (temperature / 10) as u8,
+                    (temperature % 10) as u8 | sign,
+                    0,
+                ];
+                frame[4] = frame[..4].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+                frame
+            }
+            _ => reading.to_frame(),
+        }
+    }
+}
+
+/// Guesses which model sent `frame`, by whether its values make sense in each format. A DHT11's
+/// bytes of tenths are never more than 9, and its humidity is at most 100, where a DHT22's 16 bit
+/// humidity is at most 1000 tenths and temperature 800, so at most 3 in its high bytes.
+///
+/// The DHT21, DHT22, AM2302 and RHT03 all send the same format, so any of them comes back as
+/// [`SensorModel::Dht22`]. A frame that makes sense either way (or neither) says nothing.
+pub fn guess(frame: &[u8; 5]) -> Option<SensorModel> {
+    let dht11 = frame[0] <= 100 && frame[1] <= 9 && frame[2] <= 60 && frame[3] & 0x7F <= 9;
+    let humidity = u16::from_be_bytes([frame[0], frame[1]]);
+    let temperature = u16::from_be_bytes([frame[2] & 0x7F, frame[3]]);
+    let dht22 = humidity <= 1000 && temperature <= 800;
+    match (dht11, dht22) {
+        (true, false) => Some(SensorModel::Dht11),
+        (false, true) => Some(SensorModel::Dht22),
+        _ => None,
+    }
+}
+
+/// Tallies [`guess`]es over several frames, as any one of them can be ambiguous.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Detector {
+    dht11: usize,
+    dht22: usize,
+}
+
+impl Detector {
+    /// Counts a checksum-validated frame towards the model it looks like.
+    pub fn observe(&mut self, frame: &[u8; 5]) {
+        match guess(frame) {
+            Some(SensorModel::Dht11) => self.dht11 += 1,
+            Some(_) => self.dht22 += 1,
+            None => {}
+        }
+    }
+
+    /// Returns the model most frames pointed to, unless none did or they split evenly.
+    pub fn verdict(&self) -> Option<SensorModel> {
+        match self.dht11.cmp(&self.dht22) {
+            std::cmp::Ordering::Greater => Some(SensorModel::Dht11),
+            std::cmp::Ordering::Less => Some(SensorModel::Dht22),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+/// Watches the frames from a sensor read as `model` for signs that it's really another model,
+/// whose readings would decode as plausible but wrong values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MismatchCheck {
+    model: SensorModel,
+    detector: Detector,
+    reported: bool,
+}
+
+impl MismatchCheck {
+    pub fn new(model: SensorModel) -> Self {
+        MismatchCheck {
+            model,
+            detector: Detector::default(),
+            reported: false,
+        }
+    }
+
+    /// Counts a checksum-validated frame, returning the model the frames so far point to the
+    /// first time it doesn't share `model`'s format. It isn't returned again after that.
+    pub fn observe(&mut self, frame: &[u8; 5]) -> Option<SensorModel> {
+        self.detector.observe(frame);
+        let suggested = self.detector.verdict()?;
+        if self.reported || suggested.shares_format_with(self.model) {
+            return None;
+        }
+        self.reported = true;
+        Some(suggested)
+    }
+}
+
+/// Works out which model is on `pin` from `reads` frames, calling `sleep` to wait out the
+/// sensor's cooldown after each of them, the last included, so the sensor is ready to be read as
+/// soon as this returns. Every model answers the DHT11's longer start signal, so that's what's
+/// sent. Frames the sensor garbles are skipped, but a GPIO failure is returned.
+///
+/// Returns `None` if the frames didn't settle it, and [`SensorModel::Dht22`] for any of the models
+/// that share its format.
+pub fn detect(
+    pin: &mut impl SensorPin,
+    reads: usize,
+    timeout: Duration,
+    mut sleep: impl FnMut(Duration),
+) -> Result<Option<SensorModel>, Dht22Error> {
+    // The DHT11 needs the longest start signal, and the others the longest cooldown.
+    let start_signal = SensorModel::Dht11.start_signal();
+    let cooldown = SensorModel::Dht22.min_interval();
+
+    let mut detector = Detector::default();
+    for _ in 0..reads {
+        match dht22::read_frame(pin, start_signal, timeout) {
+            Ok(frame) => detector.observe(&frame),
+            Err(e) if e.is_transient() => {}
+            Err(e) => return Err(e),
+        }
+        sleep(cooldown);
+    }
+    Ok(detector.verdict())
+}
+
+impl FromStr for SensorModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dht11" => Ok(SensorModel::Dht11),
+            "dht21" | "am2301" => Ok(SensorModel::Dht21),
+            "dht22" => Ok(SensorModel::Dht22),
+            "am2302" => Ok(SensorModel::Am2302),
+            "rht03" => Ok(SensorModel::Rht03),
+            _ => Err(format!(
+                "expected one of dht11, dht21, am2301, dht22, am2302 or rht03, got {s:?}"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SensorModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SensorModel::Dht11 => "dht11",
+            SensorModel::Dht21 => "dht21",
+            SensorModel::Dht22 => "dht22",
+            SensorModel::Am2302 => "am2302",
+            SensorModel::Rht03 => "rht03",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht22::DEFAULT_TIMEOUT;
+    use crate::gpiod::GpiodError;
+    use crate::pin::CapturedEdge;
+    use crate::sim::SimulatedDht22;
+    use simple_test_case::test_case;
+    use std::cell::RefCell;
+
+    fn reading(humidity_percent: f32, temperature_celsius: f32) -> Reading {
+        Reading {
+            humidity_percent,
+            temperature_celsius,
+        }
+    }
+
+    #[test_case([0x41, 0x02, 0x17, 0x05, 0x5F], 65.2, 23.5; "positive temperature")]
+    #[test_case([0x32, 0x00, 0x01, 0x83, 0xB6], 50.0, -1.3; "negative temperature")]
+    #[test_case([0x14, 0x00, 0x00, 0x00, 0x14], 20.0, 0.0; "integers only")]
+    #[test]
+    fn test_dht11(frame: [u8; 5], humidity: f32, temperature: f32) {
+        let reading = SensorModel::Dht11.decode(&frame);
+        assert_eq!(reading.humidity_percent, humidity);
+        assert_eq!(reading.temperature_celsius, temperature);
+        assert_eq!(SensorModel::Dht11.encode(reading), frame);
+    }
+
+    #[test_case(SensorModel::Dht21; "dht21")]
+    #[test_case(SensorModel::Dht22; "dht22")]
+    #[test_case(SensorModel::Am2302; "am2302")]
+    #[test_case(SensorModel::Rht03; "rht03")]
+    #[test]
+    fn test_tenths(model: SensorModel) {
+        let frame = [0x02, 0x8C, 0x81, 0x5F, 0x6E];
+        let reading = model.decode(&frame);
+        assert_eq!(reading.humidity_percent, 65.2);
+        assert_eq!(reading.temperature_celsius, -35.1);
+        assert_eq!(model.encode(reading), frame);
+    }
+
+    #[test]
+    fn test_timings() {
+        assert_eq!(SensorModel::Dht11.start_signal(), Duration::from_millis(18));
+        assert_eq!(SensorModel::Dht11.min_interval(), Duration::from_secs(1));
+        assert_eq!(SensorModel::Dht22.start_signal(), Duration::from_millis(1));
+        assert_eq!(SensorModel::Dht22.min_interval(), Duration::from_secs(2));
+    }
+
+    #[test_case([0x2D, 0x00, 0x15, 0x03, 0x45], Some(SensorModel::Dht11); "dht11")]
+    #[test_case([0x32, 0x00, 0x01, 0x83, 0xB6], Some(SensorModel::Dht11); "dht11 below zero")]
+    #[test_case([0x02, 0x8C, 0x01, 0x5F, 0xEE], Some(SensorModel::Dht22); "dht22")]
+    #[test_case([0x01, 0xF4, 0x80, 0x65, 0xDA], Some(SensorModel::Dht22); "dht22 below zero")]
+    #[test_case([0x02, 0x00, 0x01, 0x00, 0x03], None; "either")]
+    #[test_case([0xFF, 0xFF, 0xFF, 0xFF, 0xFC], None; "neither")]
+    #[test]
+    fn test_guess(frame: [u8; 5], expected: Option<SensorModel>) {
+        assert_eq!(guess(&frame), expected);
+    }
+
+    #[test]
+    fn test_detector() {
+        let mut detector = Detector::default();
+        assert_eq!(detector.verdict(), None);
+
+        detector.observe(&[0x2D, 0x00, 0x15, 0x03, 0x45]);
+        detector.observe(&[0x02, 0x00, 0x01, 0x00, 0x03]);
+        assert_eq!(detector.verdict(), Some(SensorModel::Dht11));
+
+        detector.observe(&[0x02, 0x8C, 0x01, 0x5F, 0xEE]);
+        assert_eq!(detector.verdict(), None);
+    }
+
+    #[test]
+    fn test_mismatch_check() {
+        let dht11 = [0x2D, 0x00, 0x15, 0x03, 0x45];
+        let mut check = MismatchCheck::new(SensorModel::Dht22);
+        assert_eq!(check.observe(&dht11), Some(SensorModel::Dht11));
+        // Only the first mismatch is worth mentioning.
+        assert_eq!(check.observe(&dht11), None);
+
+        let mut check = MismatchCheck::new(SensorModel::Dht11);
+        assert_eq!(check.observe(&dht11), None);
+        let mut check = MismatchCheck::new(SensorModel::Am2302);
+        assert_eq!(check.observe(&[0x02, 0x8C, 0x01, 0x5F, 0xEE]), None);
+    }
+
+    #[test_case(SensorModel::Dht11, SensorModel::Dht11; "dht11")]
+    #[test_case(SensorModel::Dht22, SensorModel::Dht22; "dht22")]
+    #[test_case(SensorModel::Am2302, SensorModel::Dht22; "same format as dht22")]
+    #[test]
+    fn test_detect(attached: SensorModel, expected: SensorModel) {
+        let mut sensor = SimulatedDht22::of_model(attached, reading(45.0, 21.3));
+        let mut slept = vec![];
+        let detected = detect(&mut sensor, 3, DEFAULT_TIMEOUT, |d| slept.push(d)).unwrap();
+        assert_eq!(detected, Some(expected));
+        assert_eq!(slept, [Duration::from_secs(2); 3]);
+    }
+
+    // Logs the start of each read on the pin it wraps, so the order of reads and sleeps can be
+    // checked.
+    struct LoggingPin<'a> {
+        pin: SimulatedDht22,
+        log: &'a RefCell<Vec<&'static str>>,
+    }
+
+    impl SensorPin for LoggingPin<'_> {
+        fn drive_low(&mut self) -> Result<(), GpiodError> {
+            self.log.borrow_mut().push("read");
+            self.pin.drive_low()
+        }
+
+        fn release(&mut self) -> Result<(), GpiodError> {
+            self.pin.release()
+        }
+
+        fn wait_edges(&mut self, timeout: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+            self.pin.wait_edges(timeout)
+        }
+
+        fn now(&self) -> Result<Duration, GpiodError> {
+            self.pin.now()
+        }
+    }
+
+    #[test]
+    fn test_detect_cools_down_before_first_read() {
+        let log = RefCell::new(vec![]);
+        let mut pin = LoggingPin {
+            pin: SimulatedDht22::new(reading(45.0, 21.3)),
+            log: &log,
+        };
+        detect(&mut pin, 2, DEFAULT_TIMEOUT, |_| {
+            log.borrow_mut().push("sleep")
+        })
+        .unwrap();
+        dht22::read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap();
+        assert_eq!(*log.borrow(), ["read", "sleep", "read", "sleep", "read"]);
+    }
+
+    #[test]
+    fn test_detect_no_response() {
+        let mut sensor = SimulatedDht22::new(reading(45.0, 21.3)).without_response();
+        assert_eq!(
+            detect(&mut sensor, 3, DEFAULT_TIMEOUT, |_| {}).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_shares_format_with() {
+        assert!(SensorModel::Dht22.shares_format_with(SensorModel::Rht03));
+        assert!(SensorModel::Dht11.shares_format_with(SensorModel::Dht11));
+        assert!(!SensorModel::Dht11.shares_format_with(SensorModel::Dht21));
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        for model in SensorModel::ALL {
+            assert_eq!(model.to_string().parse::<SensorModel>().unwrap(), model);
+        }
+    }
+
+    #[test_case("AM2301", SensorModel::Dht21; "alias")]
+    #[test_case("DHT11", SensorModel::Dht11; "upper case")]
+    #[test]
+    fn test_parse(s: &str, expected: SensorModel) {
+        assert_eq!(s.parse::<SensorModel>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("dht33".parse::<SensorModel>().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::io;
+use std::time::Duration;
+
+use crate::gpiod::{
+    Bias, Chip, Direction, Drive, Edge, EdgeEventBuffer, EdgeKind, GpiodError, IGpiod, LineConfig,
+    LineRequest, LineSettings, Offset, RequestConfig, Value,
+};
+
+/// An edge seen on a sensor's line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapturedEdge {
+    pub offset: Offset,
+    pub kind: EdgeKind,
+    /// When the edge happened, on the monotonic clock.
+    pub timestamp: Duration,
+    /// Counts edges on the line since it was requested, so a gap means the kernel dropped some.
+    pub seqno: u64,
+}
+
+/// The single data pin a sensor is wired to, as the sensor protocol sees it. The pin is
+/// open-drain: the host can only pull it low or let go of it, after which the pull-up or the
+/// sensor decide its level.
+pub trait SensorPin {
+    /// Pulls the line low.
+    fn drive_low(&mut self) -> Result<(), GpiodError>;
+
+    /// Lets go of the line and starts timestamping its edges.
+    fn release(&mut self) -> Result<(), GpiodError>;
+
+    /// Waits up to `timeout` for the line to change, returning the edges seen since the last
+    /// call. No edges means the line was quiet for `timeout`.
+    fn wait_edges(&mut self, timeout: Duration) -> Result<Vec<CapturedEdge>, GpiodError>;
+
+    /// Returns the current time on the clock edges are timestamped with.
+    fn now(&self) -> Result<Duration, GpiodError>;
+}
+
+/// How many edge events the kernel should buffer for a sensor line. A full frame arrives in ~5ms,
+/// which can be faster than we get scheduled to read it.
+pub const EVENT_BUFFER_SIZE: usize = 128;
+
+/// A [`SensorPin`] backed by a libgpiod line request, with edges timestamped by the kernel.
+pub struct GpiodPin {
+    request: LineRequest,
+    buffer: EdgeEventBuffer,
+    low: LineConfig,
+    released: LineConfig,
+}
+
+impl GpiodPin {
+    /// Requests the line at `offset` on `chip`. The line starts out released.
+    pub fn new(gpiod: &impl IGpiod, chip: &Chip, offset: Offset) -> Result<Self, GpiodError> {
+        let low = output_config(offset)?;
+        let released = input_config(offset)?;
+
+        let mut req_cfg = RequestConfig::new()?;
+        req_cfg.set_event_buffer_size(EVENT_BUFFER_SIZE);
+        let request = gpiod.chip_request_lines(chip, &req_cfg, &released)?;
+
+        Ok(GpiodPin {
+            request,
+            buffer: EdgeEventBuffer::new(EVENT_BUFFER_SIZE)?,
+            low,
+            released,
+        })
+    }
+}
+
+impl SensorPin for GpiodPin {
+    fn drive_low(&mut self) -> Result<(), GpiodError> {
+        self.request.reconfigure(&self.low)?;
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), GpiodError> {
+        self.request.reconfigure(&self.released)?;
+        Ok(())
+    }
+
+    fn wait_edges(&mut self, timeout: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+        if !self.request.wait_edge_events(timeout)? {
+            return Ok(vec![]);
+        }
+        self.request.read_edge_events(&mut self.buffer)?;
+        self.buffer
+            .iter()
+            .map(|event| {
+                Ok(CapturedEdge {
+                    offset: event.line_offset(),
+                    kind: event.kind()?,
+                    timestamp: event.timestamp(),
+                    seqno: event.line_seqno(),
+                })
+            })
+            .collect()
+    }
+
+    fn now(&self) -> Result<Duration, GpiodError> {
+        // Edges are timestamped on the monotonic clock unless the line is set up otherwise.
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // Safety: `now` is a valid timespec to write to.
+        if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) } != 0 {
+            return Err(GpiodError::Clock(io::Error::last_os_error()));
+        }
+        Ok(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+    }
+}
+
+// An open-drain output driven low. Open-drain so the host never drives the line high against the
+// sensor.
+fn output_config(offset: Offset) -> Result<LineConfig, GpiodError> {
+    let mut settings = LineSettings::new()?;
+    settings
+        .set_direction(Direction::Output)?
+        .set_bias(Bias::Disabled)?
+        .set_drive(Drive::OpenDrain)?
+        .set_output_value(Value::Inactive)?;
+
+    let mut config = LineConfig::new()?;
+    config.add_line_settings(&[offset], &settings)?;
+    Ok(config)
+}
+
+// An input reporting both edges, leaving the pull-up to hold the line high.
+fn input_config(offset: Offset) -> Result<LineConfig, GpiodError> {
+    let mut settings = LineSettings::new()?;
+    settings
+        .set_direction(Direction::Input)?
+        .set_edge_detection(Edge::Both)?;
+
+    let mut config = LineConfig::new()?;
+    config.add_line_settings(&[offset], &settings)?;
+    Ok(config)
+}
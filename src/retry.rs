@@ -0,0 +1,240 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::dht22::{self, Dht22Error, Reading};
+use crate::model::SensorModel;
+use crate::pin::SensorPin;
+
+/// How many times to try a reading, and how long to wait between tries.
+///
+/// Waits double after each failed attempt, starting from `backoff`, but never drop below
+/// `cooldown` since asking the sensor sooner would only fail again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// The sensor's [`SensorModel::min_interval`].
+    pub cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::for_model(SensorModel::default())
+    }
+}
+
+/// The result of the last attempt at something, and how many attempts it took.
+#[derive(Debug)]
+pub struct Outcome<T> {
+    pub result: Result<T, Dht22Error>,
+    pub attempts: u32,
+    /// When the last attempt started. The sensor needs its cooldown from here before it's asked
+    /// again.
+    pub last_attempt: Instant,
+}
+
+impl RetryPolicy {
+    /// The default policy for a `model` sensor, which backs off from its minimum interval.
+    pub fn for_model(model: SensorModel) -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: model.min_interval(),
+            max_backoff: Duration::from_secs(8),
+            cooldown: model.min_interval(),
+        }
+    }
+
+    /// A policy that makes a single attempt.
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Returns how long to wait after the `attempt`th attempt (counting from 1) fails.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let doubled = self
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        doubled.min(self.max_backoff).max(self.cooldown)
+    }
+
+    /// Takes a reading from the `model` sensor on `pin`, retrying transient failures.
+    pub fn read(
+        &self,
+        pin: &mut impl SensorPin,
+        model: SensorModel,
+        timeout: Duration,
+    ) -> Outcome<Reading> {
+        self.retry(|| dht22::read(pin, model, timeout), thread::sleep)
+    }
+
+    /// Runs `attempt` until it succeeds, fails for good, or runs out of attempts, calling `sleep`
+    /// to wait between them.
+    pub fn retry<T>(
+        &self,
+        attempt: impl FnMut() -> Result<T, Dht22Error>,
+        mut sleep: impl FnMut(Duration),
+    ) -> Outcome<T> {
+        self.retry_until(attempt, |delay| {
+            sleep(delay);
+            true
+        })
+    }
+
+    /// Like [`RetryPolicy::retry`], but gives up early, with the last attempt's failure, when
+    /// `sleep` returns false. That lets a wait be cut short by whatever it was waiting on.
+    pub fn retry_until<T>(
+        &self,
+        mut attempt: impl FnMut() -> Result<T, Dht22Error>,
+        mut sleep: impl FnMut(Duration) -> bool,
+    ) -> Outcome<T> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let last_attempt = Instant::now();
+            let result = attempt();
+            match &result {
+                Err(e)
+                    if e.is_transient()
+                        && attempts < self.max_attempts
+                        && sleep(self.delay(attempts)) => {}
+                _ => {
+                    return Outcome {
+                        result,
+                        attempts,
+                        last_attempt,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht22::DEFAULT_TIMEOUT;
+    use crate::gpiod::GpiodError;
+    use crate::sim::SimulatedDht22;
+    use simple_test_case::test_case;
+
+    const READING: Reading = Reading {
+        humidity_percent: 65.2,
+        temperature_celsius: 35.1,
+    };
+
+    fn checksum() -> Dht22Error {
+        Dht22Error::Checksum {
+            expected: 1,
+            actual: 2,
+        }
+    }
+
+    #[test_case(1, 2; "first retry")]
+    #[test_case(2, 4; "second retry")]
+    #[test_case(3, 8; "third retry")]
+    #[test_case(4, 8; "capped")]
+    #[test_case(40, 8; "capped without overflow")]
+    #[test]
+    fn test_delay(attempt: u32, secs: u64) {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(attempt), Duration::from_secs(secs));
+    }
+
+    #[test]
+    fn test_for_model() {
+        let policy = RetryPolicy::for_model(SensorModel::Dht11);
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_delay_respects_cooldown() {
+        let policy = RetryPolicy {
+            backoff: Duration::from_millis(100),
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let mut results = vec![
+            Err(Dht22Error::ShortFrame(12)),
+            Err(checksum()),
+            Ok(READING),
+        ]
+        .into_iter();
+        let mut slept = vec![];
+        let outcome = RetryPolicy::default().retry(|| results.next().unwrap(), |d| slept.push(d));
+
+        assert_eq!(outcome.result.unwrap(), READING);
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(slept, [Duration::from_secs(2), Duration::from_secs(4)]);
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let mut slept = vec![];
+        let outcome = RetryPolicy::default().retry(
+            || Err::<Reading, _>(Dht22Error::ShortFrame(0)),
+            |d| slept.push(d),
+        );
+        assert!(matches!(outcome.result, Err(Dht22Error::ShortFrame(0))));
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(slept.len(), 2);
+    }
+
+    #[test]
+    fn test_does_not_retry_fatal_errors() {
+        let mut slept = vec![];
+        let outcome = RetryPolicy::default().retry(
+            || {
+                Err::<Reading, _>(Dht22Error::Gpiod(GpiodError::Reconfigure {
+                    chip: "/dev/gpiochip0".into(),
+                    offsets: vec![4],
+                    source: std::io::ErrorKind::ResourceBusy.into(),
+                }))
+            },
+            |d| slept.push(d),
+        );
+        assert!(outcome.result.is_err());
+        assert_eq!(outcome.attempts, 1);
+        assert!(slept.is_empty());
+    }
+
+    #[test]
+    fn test_retry_until_gives_up_early() {
+        let mut slept = 0;
+        let outcome = RetryPolicy::default().retry_until(
+            || Err::<Reading, _>(Dht22Error::ShortFrame(0)),
+            |_| {
+                slept += 1;
+                false
+            },
+        );
+        assert!(matches!(outcome.result, Err(Dht22Error::ShortFrame(0))));
+        assert_eq!(outcome.attempts, 1);
+        assert_eq!(slept, 1);
+    }
+
+    #[test]
+    fn test_never() {
+        let outcome = RetryPolicy::never().retry(
+            || Err::<Reading, _>(Dht22Error::ShortFrame(0)),
+            |_| panic!("should not wait"),
+        );
+        assert_eq!(outcome.attempts, 1);
+    }
+
+    #[test]
+    fn test_read() {
+        let mut pin = SimulatedDht22::new(READING);
+        let outcome = RetryPolicy::default().read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT);
+        assert_eq!(outcome.result.unwrap(), READING);
+        assert_eq!(outcome.attempts, 1);
+    }
+}
@@ -0,0 +1,380 @@
+//! A simulated sensor, for exercising the read path without hardware. It models the sensor's
+//! side of the protocol as a [`SensorPin`]: it waits for a start signal long enough for its
+//! model, then answers with the ack pulse and a 40 bit frame timed as the datasheet gives, on a
+//! clock of its own.
+//!
+//! Faults that show up in the field can be injected: a sensor that never answers
+//! ([`SimulatedDht22::without_response`]), a frame that fails its checksum
+//! ([`SimulatedDht22::with_bad_checksum`]), edges the host missed
+//! ([`SimulatedDht22::with_missing_bits`]) and pulse widths that wander
+//! ([`SimulatedDht22::with_jitter`]).
+
+use std::time::{Duration, Instant};
+
+use crate::dht22::{Reading, FRAME_BITS};
+use crate::gpiod::{EdgeKind, GpiodError};
+use crate::model::SensorModel;
+use crate::pin::{CapturedEdge, SensorPin};
+
+// Timings from the datasheet, as sent by a well-behaved sensor.
+const RESPONSE_DELAY: Duration = Duration::from_micros(30);
+const ACK: Duration = Duration::from_micros(80);
+const BIT_LOW: Duration = Duration::from_micros(50);
+const ZERO_HIGH: Duration = Duration::from_micros(27);
+const ONE_HIGH: Duration = Duration::from_micros(70);
+
+// How many edges a single wait hands back, like a libgpiod edge event buffer.
+const BATCH_SIZE: usize = 64;
+
+/// An in-process DHT22 that answers the host's start signal with a frame of timestamped edges,
+/// for testing the read path without hardware.
+///
+/// Timestamps come from a simulated clock rather than the system one, so edges are exactly as
+/// configured no matter how the test is scheduled. Faults are added with the `with_*` methods:
+///
+/// ```ignore
+/// let mut sensor = SimulatedDht22::new(reading)
+///     .with_jitter(Duration::from_micros(10), 42)
+///     .with_missing_bits(&[7]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SimulatedDht22 {
+    frame: [u8; 5],
+    // The sensor ignores start signals shorter than this.
+    min_start_signal: Duration,
+    responds: bool,
+    jitter: Duration,
+    rng: u64,
+    missing_bits: Vec<usize>,
+    now: Duration,
+    low_since: Option<Instant>,
+    pending: Vec<(EdgeKind, Duration)>,
+    seqno: u64,
+}
+
+impl SimulatedDht22 {
+    /// Creates a sensor that sends `reading` with a valid checksum.
+    pub fn new(reading: Reading) -> Self {
+        Self::of_model(SensorModel::Dht22, reading)
+    }
+
+    /// Creates a `model` sensor that sends `reading` in its format, with a valid checksum. It only
+    /// answers start signals long enough for that model.
+    pub fn of_model(model: SensorModel, reading: Reading) -> Self {
+        SimulatedDht22 {
+            min_start_signal: model.start_signal(),
+            ..Self::from_frame(model.encode(reading))
+        }
+    }
+
+    /// Creates a DHT22 that sends `frame` exactly, checksum byte included.
+    pub fn from_frame(frame: [u8; 5]) -> Self {
+        SimulatedDht22 {
+            frame,
+            min_start_signal: SensorModel::Dht22.start_signal(),
+            responds: true,
+            jitter: Duration::ZERO,
+            rng: 1,
+            missing_bits: vec![],
+            // Start well clear of zero, so jitter can never take a timestamp below it.
+            now: Duration::from_secs(1),
+            low_since: None,
+            pending: vec![],
+            seqno: 0,
+        }
+    }
+
+    /// Lengthens or shortens every pulse by up to `jitter`. The same `seed` always gives the same
+    /// pulses.
+    pub fn with_jitter(mut self, jitter: Duration, seed: u64) -> Self {
+        self.jitter = jitter;
+        // xorshift gets stuck on zero.
+        self.rng = seed.max(1);
+        self
+    }
+
+    /// Drops the edges around the high pulse of each of `bits`, as if they were never seen.
+    pub fn with_missing_bits(mut self, bits: &[usize]) -> Self {
+        self.missing_bits = bits.to_vec();
+        self
+    }
+
+    /// Corrupts the checksum byte so the frame no longer adds up.
+    pub fn with_bad_checksum(mut self) -> Self {
+        self.frame[4] = self.frame[4].wrapping_add(1);
+        self
+    }
+
+    /// Never answers the start signal, as if disconnected.
+    pub fn without_response(mut self) -> Self {
+        self.responds = false;
+        self
+    }
+
+    /// Returns the frame the sensor sends.
+    pub fn frame(&self) -> [u8; 5] {
+        self.frame
+    }
+
+    // xorshift64, which is plenty for spreading pulse widths about.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn jittered(&mut self, width: Duration) -> Duration {
+        let jitter = self.jitter.as_nanos() as u64;
+        if jitter == 0 {
+            return width;
+        }
+        let offset = self.next_random() % (2 * jitter + 1);
+        (width + Duration::from_nanos(offset)).saturating_sub(self.jitter)
+    }
+
+    fn respond(&mut self) {
+        // Each pulse is a low then a high, and the ack is the first.
+        let mut pulses = vec![(ACK, ACK, false)];
+        for bit in 0..FRAME_BITS {
+            let high = if self.frame[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                ONE_HIGH
+            } else {
+                ZERO_HIGH
+            };
+            pulses.push((BIT_LOW, high, self.missing_bits.contains(&bit)));
+        }
+        // The sensor holds the line low once more, then lets go of it for good.
+        pulses.push((BIT_LOW, Duration::ZERO, false));
+
+        let mut now = self.now + RESPONSE_DELAY;
+        // A high pulse runs from its rising edge to the next pulse's falling edge, so hiding one
+        // means dropping both.
+        let mut hide_fall = false;
+        for (low, high, hidden) in pulses {
+            let low = self.jittered(low);
+            let high = self.jittered(high);
+            if !hide_fall {
+                self.pending.push((EdgeKind::Falling, now));
+            }
+            if !hidden {
+                self.pending.push((EdgeKind::Rising, now + low));
+            }
+            hide_fall = hidden;
+            now += low + high;
+        }
+    }
+}
+
+impl SensorPin for SimulatedDht22 {
+    fn drive_low(&mut self) -> Result<(), GpiodError> {
+        self.pending.clear();
+        self.low_since = Some(Instant::now());
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), GpiodError> {
+        let Some(since) = self.low_since.take() else {
+            return Ok(());
+        };
+        let held = since.elapsed();
+        self.now += held;
+        if self.responds && held >= self.min_start_signal {
+            self.respond();
+        }
+        Ok(())
+    }
+
+    fn wait_edges(&mut self, _: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+        let num = self.pending.len().min(BATCH_SIZE);
+        // By the time an edge is handed out, it has happened.
+        if let Some((_, timestamp)) = self.pending[..num].last() {
+            self.now = self.now.max(*timestamp);
+        }
+        let edges = self.pending.drain(..num).map(|(kind, timestamp)| {
+            self.seqno += 1;
+            CapturedEdge {
+                // The simulated sensor is the only thing on its line.
+                offset: 0,
+                kind,
+                timestamp,
+                seqno: self.seqno,
+            }
+        });
+        Ok(edges.collect())
+    }
+
+    fn now(&self) -> Result<Duration, GpiodError> {
+        // The clock keeps running while the host holds the line low.
+        let held = self.low_since.map(|since| since.elapsed());
+        Ok(self.now + held.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht22::{self, Dht22Error, DEFAULT_TIMEOUT};
+    use simple_test_case::test_case;
+
+    fn reading(humidity_percent: f32, temperature_celsius: f32) -> Reading {
+        Reading {
+            humidity_percent,
+            temperature_celsius,
+        }
+    }
+
+    #[test_case(reading(65.2, 35.1); "positive temperature")]
+    #[test_case(reading(50.0, -10.1); "negative temperature")]
+    #[test_case(reading(0.0, 0.0); "all zero")]
+    #[test]
+    fn test_read(expected: Reading) {
+        let mut sensor = SimulatedDht22::new(expected);
+        assert_eq!(
+            dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap(),
+            expected
+        );
+    }
+
+    #[test_case(SensorModel::Dht11; "dht11")]
+    #[test_case(SensorModel::Dht21; "dht21")]
+    #[test_case(SensorModel::Am2302; "am2302")]
+    #[test]
+    fn test_read_model(model: SensorModel) {
+        let expected = reading(45.0, -2.3);
+        let mut sensor = SimulatedDht22::of_model(model, expected);
+        assert_eq!(
+            dht22::read(&mut sensor, model, DEFAULT_TIMEOUT).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_dht11_ignores_dht22_start_signal() {
+        let mut sensor = SimulatedDht22::of_model(SensorModel::Dht11, reading(45.0, 21.0));
+        assert!(matches!(
+            dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT),
+            Err(Dht22Error::ShortFrame(0))
+        ));
+    }
+
+    #[test_case(1; "seed 1")]
+    #[test_case(42; "seed 42")]
+    #[test_case(0xDEAD_BEEF; "seed 0xdeadbeef")]
+    #[test]
+    fn test_read_with_jitter(seed: u64) {
+        // Short of the 23us it takes to push either bit width across the threshold.
+        let expected = reading(65.2, 35.1);
+        let mut sensor = SimulatedDht22::new(expected).with_jitter(Duration::from_micros(15), seed);
+        assert_eq!(
+            dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_read_repeatedly() {
+        let expected = reading(65.2, 35.1);
+        let mut sensor = SimulatedDht22::new(expected);
+        for _ in 0..3 {
+            assert_eq!(
+                dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut sensor = SimulatedDht22::new(reading(65.2, 35.1)).with_bad_checksum();
+        assert_eq!(sensor.frame(), [0x02, 0x8C, 0x01, 0x5F, 0xEF]);
+        assert!(matches!(
+            dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT),
+            Err(Dht22Error::Checksum {
+                expected: 0xEF,
+                actual: 0xEE
+            })
+        ));
+    }
+
+    #[test]
+    fn test_no_response() {
+        let mut sensor = SimulatedDht22::new(reading(65.2, 35.1)).without_response();
+        assert!(matches!(
+            dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT),
+            Err(Dht22Error::ShortFrame(0))
+        ));
+    }
+
+    #[test]
+    fn test_short_start_signal_is_ignored() {
+        let mut sensor = SimulatedDht22::new(reading(65.2, 35.1));
+        sensor.drive_low().unwrap();
+        sensor.release().unwrap();
+        assert!(sensor.wait_edges(DEFAULT_TIMEOUT).unwrap().is_empty());
+    }
+
+    #[test_case(&[0]; "first bit")]
+    #[test_case(&[20, 21]; "two bits")]
+    #[test]
+    fn test_missing_bits(bits: &[usize]) {
+        let mut sensor = SimulatedDht22::new(reading(65.2, 35.1)).with_missing_bits(bits);
+        assert!(dht22::read(&mut sensor, SensorModel::Dht22, DEFAULT_TIMEOUT).is_err());
+    }
+
+    // Collects every edge from one response.
+    fn response(sensor: &mut SimulatedDht22) -> Vec<(EdgeKind, Duration)> {
+        sensor.drive_low().unwrap();
+        std::thread::sleep(sensor.min_start_signal);
+        sensor.release().unwrap();
+        let mut edges = vec![];
+        loop {
+            let batch = sensor.wait_edges(DEFAULT_TIMEOUT).unwrap();
+            if batch.is_empty() {
+                return edges;
+            }
+            edges.extend(batch.into_iter().map(|e| (e.kind, e.timestamp)));
+        }
+    }
+
+    #[test]
+    fn test_response_edges() {
+        let edges = response(&mut SimulatedDht22::new(reading(65.2, 35.1)));
+        // The ack, a pulse per bit, and the final low.
+        assert_eq!(edges.len(), 2 * (FRAME_BITS + 2));
+        assert!(edges
+            .iter()
+            .step_by(2)
+            .all(|(kind, _)| *kind == EdgeKind::Falling));
+        assert!(edges.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert_eq!(dht22::high_pulses(&edges)[0], ACK);
+    }
+
+    #[test]
+    fn test_missing_bit_edges() {
+        let edges = response(&mut SimulatedDht22::new(reading(65.2, 35.1)).with_missing_bits(&[5]));
+        assert_eq!(edges.len(), 2 * (FRAME_BITS + 1));
+        assert_eq!(dht22::high_pulses(&edges).len(), FRAME_BITS);
+    }
+
+    #[test]
+    fn test_jitter_is_deterministic() {
+        let sensor =
+            SimulatedDht22::new(reading(65.2, 35.1)).with_jitter(Duration::from_micros(5), 7);
+        let widths = |edges: Vec<(EdgeKind, Duration)>| dht22::high_pulses(&edges);
+
+        let first = widths(response(&mut sensor.clone()));
+        let second = widths(response(&mut sensor.clone()));
+        assert_eq!(first, second);
+        assert!(first
+            .iter()
+            .zip(widths(response(&mut SimulatedDht22::new(reading(
+                65.2, 35.1
+            )))))
+            .all(|(jittered, exact)| jittered.abs_diff(exact) <= Duration::from_micros(10)));
+        assert_ne!(
+            first,
+            widths(response(&mut SimulatedDht22::new(reading(65.2, 35.1))))
+        );
+    }
+}
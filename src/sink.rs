@@ -0,0 +1,259 @@
+//! Places the daemon publishes readings to.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use crate::dht22::Reading;
+use crate::format::Formatter;
+
+/// A reading along with when it was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Sample {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub reading: Reading,
+    /// How many attempts the reading took.
+    pub attempts: u32,
+}
+
+impl Sample {
+    /// Timestamps a reading that was just taken.
+    pub fn now(reading: Reading, attempts: u32) -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Sample {
+            // Milliseconds since the epoch fit a u64 for the next few hundred million years.
+            timestamp_ms: since_epoch.as_millis() as u64,
+            reading,
+            attempts,
+        }
+    }
+}
+
+impl fmt::Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reading)?;
+        // Most readings work first time, and don't need to say so.
+        if self.attempts > 1 {
+            write!(f, " ({} attempts)", self.attempts)?;
+        }
+        Ok(())
+    }
+}
+
+/// Somewhere readings are published to.
+pub trait Sink {
+    fn publish(&mut self, sample: &Sample) -> io::Result<()>;
+}
+
+/// Writes each reading as a line in whichever format `--format` picked, as `read` prints them.
+pub struct FormattedSink<W: Write> {
+    out: W,
+    formatter: Formatter,
+    started: bool,
+}
+
+impl<W: Write> FormattedSink<W> {
+    pub fn new(out: W, formatter: Formatter) -> Self {
+        FormattedSink {
+            out,
+            formatter,
+            started: false,
+        }
+    }
+}
+
+impl<W: Write> Sink for FormattedSink<W> {
+    fn publish(&mut self, sample: &Sample) -> io::Result<()> {
+        // The header waits for the first reading, so nothing is written if there isn't one.
+        if !self.started {
+            self.formatter.write_header(&mut self.out)?;
+            self.started = true;
+        }
+        self.formatter.write_sample(&mut self.out, sample)?;
+        self.out.flush()
+    }
+}
+
+/// Writes each reading as a line of JSON.
+pub struct JsonLinesSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(out: W) -> Self {
+        JsonLinesSink { out }
+    }
+}
+
+impl<W: Write> Sink for JsonLinesSink<W> {
+    fn publish(&mut self, sample: &Sample) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, sample)?;
+        writeln!(self.out)?;
+        // Flushed every time, so whatever tails the file sees readings as they're taken.
+        self.out.flush()
+    }
+}
+
+/// A sink given on the command line: `stdout`, or `jsonl:<path>` to append JSON lines to a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkSpec {
+    Stdout,
+    JsonLines(PathBuf),
+}
+
+impl SinkSpec {
+    /// Opens the sink, creating its file if need be. Readings on stdout are written by `stdout`.
+    pub fn open(&self, stdout: &Formatter) -> io::Result<Box<dyn Sink>> {
+        Ok(match self {
+            SinkSpec::Stdout => Box::new(FormattedSink::new(io::stdout(), stdout.clone())),
+            SinkSpec::JsonLines(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Box::new(JsonLinesSink::new(BufWriter::new(file)))
+            }
+        })
+    }
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "stdout" => Ok(SinkSpec::Stdout),
+            Some(("jsonl", path)) if !path.is_empty() => Ok(SinkSpec::JsonLines(path.into())),
+            _ => Err(format!("expected `stdout` or `jsonl:<path>`, got {s:?}")),
+        }
+    }
+}
+
+impl fmt::Display for SinkSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SinkSpec::Stdout => write!(f, "stdout"),
+            SinkSpec::JsonLines(path) => write!(f, "jsonl:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{Format, Influx, Sensor};
+    use crate::model::SensorModel;
+    use simple_test_case::test_case;
+
+    const SAMPLE: Sample = Sample {
+        timestamp_ms: 1_700_000_000_123,
+        reading: Reading {
+            humidity_percent: 65.2,
+            temperature_celsius: -10.1,
+        },
+        attempts: 1,
+    };
+
+    fn formatter(format: Format) -> Formatter {
+        Formatter {
+            format,
+            sensor: Sensor {
+                chip: "/dev/gpiochip0".to_string(),
+                offset: 4,
+                model: SensorModel::Dht22,
+            },
+            influx: Influx::default(),
+        }
+    }
+
+    #[test]
+    fn test_formatted_sink() {
+        let mut out = vec![];
+        FormattedSink::new(&mut out, formatter(Format::Human))
+            .publish(&SAMPLE)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Humidity: 65.2%, Temperature: -10.1°C\n"
+        );
+    }
+
+    #[test]
+    fn test_formatted_sink_retried() {
+        let mut out = vec![];
+        let sample = Sample {
+            attempts: 3,
+            ..SAMPLE
+        };
+        FormattedSink::new(&mut out, formatter(Format::Human))
+            .publish(&sample)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Humidity: 65.2%, Temperature: -10.1°C (3 attempts)\n"
+        );
+    }
+
+    #[test]
+    fn test_formatted_sink_header_once() {
+        let mut out = vec![];
+        let mut sink = FormattedSink::new(&mut out, formatter(Format::Csv));
+        sink.publish(&SAMPLE).unwrap();
+        sink.publish(&SAMPLE).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], crate::format::CSV_HEADER);
+        assert_eq!(lines[1], lines[2]);
+    }
+
+    #[test]
+    fn test_json_lines_sink() {
+        let mut out = vec![];
+        let mut sink = JsonLinesSink::new(&mut out);
+        sink.publish(&SAMPLE).unwrap();
+        sink.publish(&SAMPLE).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(json["timestamp_ms"], 1_700_000_000_123u64);
+        assert_eq!(json["humidity_percent"].as_f64().unwrap() as f32, 65.2);
+        assert_eq!(json["temperature_celsius"].as_f64().unwrap() as f32, -10.1);
+        assert_eq!(json["attempts"], 1);
+    }
+
+    #[test_case("stdout", SinkSpec::Stdout; "stdout")]
+    #[test_case("jsonl:/var/log/dht22.jsonl", SinkSpec::JsonLines("/var/log/dht22.jsonl".into()); "json lines")]
+    #[test_case("jsonl:a:b", SinkSpec::JsonLines("a:b".into()); "colon in path")]
+    #[test]
+    fn test_parse(s: &str, expected: SinkSpec) {
+        let spec: SinkSpec = s.parse().unwrap();
+        assert_eq!(spec, expected);
+        assert_eq!(spec.to_string(), s);
+    }
+
+    #[test_case("stderr"; "unknown")]
+    #[test_case("jsonl:"; "no path")]
+    #[test_case("stdout:x"; "stdout with path")]
+    #[test]
+    fn test_parse_invalid(s: &str) {
+        assert!(s.parse::<SinkSpec>().is_err());
+    }
+
+    #[test]
+    fn test_sample_now() {
+        let sample = Sample::now(SAMPLE.reading, 2);
+        assert!(sample.timestamp_ms > SAMPLE.timestamp_ms);
+        assert_eq!(sample.reading, SAMPLE.reading);
+        assert_eq!(sample.attempts, 2);
+    }
+}
@@ -0,0 +1,10 @@
+//! Raw libgpiod bindings, generated by bindgen from `wrapper.h`. Everything here is unsafe to
+//! use directly; [`crate::gpiod`] wraps what the driver needs.
+
+#![allow(improper_ctypes)]
+#![allow(non_upper_case_globals)]
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+#![allow(dead_code)]
+
+include!("bindings/bindings.rs");
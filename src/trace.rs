@@ -0,0 +1,435 @@
+//! Recording of the edges captured during reads, so a read that failed in the field can be
+//! replayed through the decoder later.
+//!
+//! A trace file is a header followed by one capture per read. All integers are little-endian:
+//!
+//! ```text
+//! header:  magic "DHTT" | version: u8
+//! capture: edge count: u32 | edge*
+//! edge:    offset: u32 | kind: u8 (0 rising, 1 falling) | timestamp_ns: u64 | seqno: u64
+//! ```
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::gpiod::{EdgeKind, GpiodError};
+use crate::pin::{CapturedEdge, SensorPin};
+
+const MAGIC: &[u8; 4] = b"DHTT";
+const VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum TraceError {
+    #[error("Not a trace file")]
+    NotATrace,
+    #[error("Unsupported trace version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Unknown edge kind {0} in trace")]
+    UnknownEdgeKind(u8),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Writes captures to a trace, one per read.
+pub struct TraceWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Starts a trace by writing its header to `out`.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION])?;
+        Ok(TraceWriter { out })
+    }
+
+    /// Appends the edges captured during one read. The trace is flushed afterwards, so every
+    /// capture written survives the reader being killed.
+    pub fn write_capture(&mut self, edges: &[CapturedEdge]) -> io::Result<()> {
+        let len =
+            u32::try_from(edges.len()).map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+        self.out.write_all(&len.to_le_bytes())?;
+        for edge in edges {
+            let kind: u8 = match edge.kind {
+                EdgeKind::Rising => 0,
+                EdgeKind::Falling => 1,
+            };
+            // Timestamps come from a u64 of nanoseconds in the first place, so this can't truncate.
+            let timestamp_ns = edge.timestamp.as_nanos() as u64;
+            self.out.write_all(&edge.offset.to_le_bytes())?;
+            self.out.write_all(&[kind])?;
+            self.out.write_all(&timestamp_ns.to_le_bytes())?;
+            self.out.write_all(&edge.seqno.to_le_bytes())?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Reads captures back from a trace, in the order they were written.
+pub struct TraceReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Checks the header at the start of `input`.
+    pub fn new(mut input: R) -> Result<Self, TraceError> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => TraceError::NotATrace,
+            _ => e.into(),
+        })?;
+        if &magic != MAGIC {
+            return Err(TraceError::NotATrace);
+        }
+        let [version] = read_bytes(&mut input)?;
+        if version != VERSION {
+            return Err(TraceError::UnsupportedVersion(version));
+        }
+        Ok(TraceReader { input })
+    }
+
+    /// Reads the next capture, or `None` at the end of the trace.
+    pub fn read_capture(&mut self) -> Result<Option<Vec<CapturedEdge>>, TraceError> {
+        let mut len = [0u8; 4];
+        // A trace can end between captures, but not part way through one.
+        match self.input.read(&mut len[..1])? {
+            0 => return Ok(None),
+            _ => self.input.read_exact(&mut len[1..])?,
+        }
+
+        let len = u32::from_le_bytes(len);
+        let mut edges = vec![];
+        for _ in 0..len {
+            let offset = u32::from_le_bytes(read_bytes(&mut self.input)?);
+            let kind = match read_bytes(&mut self.input)? {
+                [0] => EdgeKind::Rising,
+                [1] => EdgeKind::Falling,
+                [kind] => return Err(TraceError::UnknownEdgeKind(kind)),
+            };
+            let timestamp_ns = u64::from_le_bytes(read_bytes(&mut self.input)?);
+            let seqno = u64::from_le_bytes(read_bytes(&mut self.input)?);
+            edges.push(CapturedEdge {
+                offset,
+                kind,
+                timestamp: Duration::from_nanos(timestamp_ns),
+                seqno,
+            });
+        }
+        Ok(Some(edges))
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<Vec<CapturedEdge>, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_capture().transpose()
+    }
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Counts the edges the kernel dropped from a capture, going by gaps in their sequence numbers.
+pub fn dropped_edges(edges: &[CapturedEdge]) -> u64 {
+    edges
+        .windows(2)
+        .map(|pair| {
+            pair[1]
+                .seqno
+                .saturating_sub(pair[0].seqno.saturating_add(1))
+        })
+        .sum()
+}
+
+/// A [`SensorPin`] that keeps hold of every edge seen since the start of the last read, so they
+/// can be written to a trace.
+pub struct RecordingPin<P> {
+    pin: P,
+    edges: Vec<CapturedEdge>,
+    driven_low: Option<Duration>,
+    released: Option<Duration>,
+}
+
+impl<P: SensorPin> RecordingPin<P> {
+    pub fn new(pin: P) -> Self {
+        RecordingPin {
+            pin,
+            edges: vec![],
+            driven_low: None,
+            released: None,
+        }
+    }
+
+    /// Returns the edges seen since the line was last driven low.
+    pub fn edges(&self) -> &[CapturedEdge] {
+        &self.edges
+    }
+
+    /// Returns every change to the line's level since it was last driven low, in order. Unlike
+    /// [`RecordingPin::edges`], this includes the host's own start signal, which the kernel
+    /// doesn't report as edges. Those are timestamped just before the pin changes the line, so may
+    /// be a little early.
+    pub fn transitions(&self) -> Vec<(EdgeKind, Duration)> {
+        let host = [
+            self.driven_low.map(|t| (EdgeKind::Falling, t)),
+            self.released.map(|t| (EdgeKind::Rising, t)),
+        ];
+        let mut transitions: Vec<_> = host
+            .into_iter()
+            .flatten()
+            .chain(self.edges.iter().map(|e| (e.kind, e.timestamp)))
+            .collect();
+        transitions.sort_by_key(|(_, timestamp)| *timestamp);
+        transitions
+    }
+}
+
+impl<P: SensorPin> SensorPin for RecordingPin<P> {
+    fn drive_low(&mut self) -> Result<(), GpiodError> {
+        self.edges.clear();
+        self.released = None;
+        let now = self.pin.now()?;
+        self.pin.drive_low()?;
+        self.driven_low = Some(now);
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), GpiodError> {
+        let now = self.pin.now()?;
+        self.pin.release()?;
+        self.released = Some(now);
+        Ok(())
+    }
+
+    fn wait_edges(&mut self, timeout: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+        let edges = self.pin.wait_edges(timeout)?;
+        self.edges.extend_from_slice(&edges);
+        Ok(edges)
+    }
+
+    fn now(&self) -> Result<Duration, GpiodError> {
+        self.pin.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dht22::{self, Dht22Error, Reading, DEFAULT_TIMEOUT};
+    use crate::model::SensorModel;
+    use crate::sim::SimulatedDht22;
+    use simple_test_case::test_case;
+
+    const READING: Reading = Reading {
+        humidity_percent: 65.2,
+        temperature_celsius: 35.1,
+    };
+
+    fn edge(kind: EdgeKind, timestamp_ns: u64, seqno: u64) -> CapturedEdge {
+        CapturedEdge {
+            offset: 4,
+            kind,
+            timestamp: Duration::from_nanos(timestamp_ns),
+            seqno,
+        }
+    }
+
+    // Records a read from `sensor` into a trace of its own.
+    fn record(sensor: SimulatedDht22) -> Vec<u8> {
+        let mut pin = RecordingPin::new(sensor);
+        let _ = dht22::read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT);
+        let mut writer = TraceWriter::new(vec![]).unwrap();
+        writer.write_capture(pin.edges()).unwrap();
+        writer.out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let captures = vec![
+            vec![
+                edge(EdgeKind::Falling, 1_000_030_000, 1),
+                edge(EdgeKind::Rising, 1_000_110_000, 2),
+            ],
+            vec![],
+            vec![edge(EdgeKind::Falling, u64::MAX, u64::MAX)],
+        ];
+        let mut writer = TraceWriter::new(vec![]).unwrap();
+        for capture in &captures {
+            writer.write_capture(capture).unwrap();
+        }
+
+        let trace = writer.out;
+        assert_eq!(trace.len(), 5 + 3 * 4 + 3 * 21);
+        let read: Vec<_> = TraceReader::new(&trace[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, captures);
+    }
+
+    #[test]
+    fn test_replay() {
+        let trace = record(SimulatedDht22::new(READING));
+        let captures: Vec<_> = TraceReader::new(&trace[..]).unwrap().collect();
+        assert_eq!(captures.len(), 1);
+
+        let edges = captures[0].as_ref().unwrap();
+        assert_eq!(edges.len(), 84);
+        assert_eq!(dropped_edges(edges), 0);
+        assert_eq!(
+            dht22::decode_captured(edges, SensorModel::Dht22).unwrap(),
+            READING
+        );
+    }
+
+    #[test]
+    fn test_replay_bad_checksum() {
+        let trace = record(SimulatedDht22::new(READING).with_bad_checksum());
+        let edges = TraceReader::new(&trace[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            dht22::decode_captured(&edges, SensorModel::Dht22),
+            Err(Dht22Error::Checksum { .. })
+        ));
+    }
+
+    #[test]
+    fn test_recording_restarts_each_read() {
+        let mut pin = RecordingPin::new(SimulatedDht22::new(READING));
+        dht22::read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap();
+        let first = pin.edges().to_vec();
+        dht22::read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap();
+        assert_eq!(pin.edges().len(), first.len());
+        assert!(pin.edges()[0].timestamp > first[first.len() - 1].timestamp);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut pin = RecordingPin::new(SimulatedDht22::new(READING));
+        dht22::read(&mut pin, SensorModel::Dht22, DEFAULT_TIMEOUT).unwrap();
+
+        let transitions = pin.transitions();
+        assert_eq!(transitions.len(), pin.edges().len() + 2);
+        let (start, response) = transitions.split_at(2);
+        assert_eq!(start[0].0, EdgeKind::Falling);
+        assert_eq!(start[1].0, EdgeKind::Rising);
+        assert!(start[1].1 - start[0].1 >= Duration::from_millis(1));
+        assert!(start[1].1 < response[0].1);
+    }
+
+    // A pin whose reconfigure calls each take `CALL`, on a clock of its own.
+    struct SlowPin {
+        now: Duration,
+    }
+
+    const CALL: Duration = Duration::from_millis(5);
+
+    impl SensorPin for SlowPin {
+        fn drive_low(&mut self) -> Result<(), GpiodError> {
+            self.now += CALL;
+            Ok(())
+        }
+
+        fn release(&mut self) -> Result<(), GpiodError> {
+            self.now += CALL;
+            Ok(())
+        }
+
+        fn wait_edges(&mut self, _: Duration) -> Result<Vec<CapturedEdge>, GpiodError> {
+            Ok(vec![])
+        }
+
+        fn now(&self) -> Result<Duration, GpiodError> {
+            Ok(self.now)
+        }
+    }
+
+    #[test]
+    fn test_transitions_stamped_before_change() {
+        let mut pin = RecordingPin::new(SlowPin {
+            now: Duration::ZERO,
+        });
+        pin.drive_low().unwrap();
+        pin.release().unwrap();
+        assert_eq!(
+            pin.transitions(),
+            [
+                (EdgeKind::Falling, Duration::ZERO),
+                (EdgeKind::Rising, CALL),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dropped_edges() {
+        let edges = [
+            edge(EdgeKind::Falling, 0, 7),
+            edge(EdgeKind::Rising, 1, 8),
+            edge(EdgeKind::Rising, 2, 11),
+        ];
+        assert_eq!(dropped_edges(&edges), 2);
+        assert_eq!(dropped_edges(&[]), 0);
+    }
+
+    // Traces come from files, so their sequence numbers can be anything.
+    #[test_case(5, 3; "decreasing")]
+    #[test_case(u64::MAX, u64::MAX; "max")]
+    #[test]
+    fn test_dropped_edges_out_of_order(first: u64, second: u64) {
+        let edges = [
+            edge(EdgeKind::Falling, 0, first),
+            edge(EdgeKind::Rising, 1, second),
+        ];
+        assert_eq!(dropped_edges(&edges), 0);
+    }
+
+    #[test_case(b""; "empty")]
+    #[test_case(b"DHT"; "truncated magic")]
+    #[test_case(b"VCD\n\x01"; "wrong magic")]
+    #[test]
+    fn test_not_a_trace(trace: &[u8]) {
+        assert!(matches!(
+            TraceReader::new(trace),
+            Err(TraceError::NotATrace)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        assert!(matches!(
+            TraceReader::new(&b"DHTT\x02"[..]),
+            Err(TraceError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_edge_kind() {
+        let mut trace = b"DHTT\x01\x01\x00\x00\x00\x04\x00\x00\x00\x07".to_vec();
+        trace.extend([0; 16]);
+        let mut reader = TraceReader::new(&trace[..]).unwrap();
+        assert!(matches!(
+            reader.read_capture(),
+            Err(TraceError::UnknownEdgeKind(7))
+        ));
+    }
+
+    #[test_case(3; "mid count")]
+    #[test_case(20; "mid edge")]
+    #[test]
+    fn test_truncated_capture(len: usize) {
+        let trace = record(SimulatedDht22::new(READING));
+        let mut reader = TraceReader::new(&trace[..5 + len]).unwrap();
+        assert!(
+            matches!(reader.read_capture(), Err(TraceError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof)
+        );
+    }
+}
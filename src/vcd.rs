@@ -0,0 +1,167 @@
+//! Export of the sensor line's waveform in Value Change Dump format, for viewing handshake timing
+//! in a logic analyzer viewer like GTKWave or PulseView.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::gpiod::EdgeKind;
+
+// The identifier VCD uses to refer to the line, which only needs to be unique within the file.
+const ID: char = '!';
+
+/// Writes the transitions on a single line as a VCD waveform with a nanosecond timescale.
+///
+/// Times are relative to a nanosecond before the first transition written, so the waveform
+/// starts near zero rather than however long the machine has been up, and the line's initial
+/// value has time zero to itself. Viewers only draw the last of several changes at the same time,
+/// so a transition that would land on the time of the one before is written a nanosecond after
+/// it instead.
+pub struct VcdWriter<W: Write> {
+    out: W,
+    start: Option<Duration>,
+    // When the last transition was written.
+    last: Option<u64>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    /// Writes the header declaring the line, under `name`.
+    pub fn new(mut out: W, name: &str) -> io::Result<Self> {
+        writeln!(
+            out,
+            "$version {} {} $end",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        writeln!(out, "$timescale 1ns $end")?;
+        writeln!(out, "$scope module dht22 $end")?;
+        // Names can't contain whitespace, which would end them early.
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        writeln!(out, "$var wire 1 {ID} {name} $end")?;
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+        Ok(VcdWriter {
+            out,
+            start: None,
+            last: None,
+        })
+    }
+
+    /// Appends `transitions`, which must be in order and no earlier than any already written. The
+    /// line is assumed to idle high until the first one.
+    pub fn write_transitions(&mut self, transitions: &[(EdgeKind, Duration)]) -> io::Result<()> {
+        for (kind, timestamp) in transitions {
+            let start = match self.start {
+                Some(start) => start,
+                None => {
+                    // The pull-up holds the line high until something pulls it low, which the
+                    // first transition does just after.
+                    writeln!(self.out, "#0")?;
+                    writeln!(self.out, "$dumpvars 1{ID} $end")?;
+                    *self
+                        .start
+                        .insert(timestamp.saturating_sub(Duration::from_nanos(1)))
+                }
+            };
+
+            // Nanoseconds since the start fit a u64 for the next 584 years.
+            let mut time = timestamp.saturating_sub(start).as_nanos() as u64;
+            time = time.max(self.last.map_or(1, |last| last + 1));
+            writeln!(self.out, "#{time}")?;
+            self.last = Some(time);
+            let value = match kind {
+                EdgeKind::Rising => '1',
+                EdgeKind::Falling => '0',
+            };
+            writeln!(self.out, "{value}{ID}")?;
+        }
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = concat!(
+        "$version dht22_reader ",
+        env!("CARGO_PKG_VERSION"),
+        " $end\n\
+         $timescale 1ns $end\n\
+         $scope module dht22 $end\n\
+         $var wire 1 ! GPIO4 $end\n\
+         $upscope $end\n\
+         $enddefinitions $end\n"
+    );
+
+    fn output(writer: VcdWriter<Vec<u8>>) -> String {
+        String::from_utf8(writer.out).unwrap()
+    }
+
+    #[test]
+    fn test_header() {
+        let writer = VcdWriter::new(vec![], "GPIO4").unwrap();
+        assert_eq!(output(writer), HEADER);
+    }
+
+    #[test]
+    fn test_name_whitespace() {
+        let writer = VcdWriter::new(vec![], "sensor data").unwrap();
+        assert!(output(writer).contains("$var wire 1 ! sensor_data $end\n"));
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut writer = VcdWriter::new(vec![], "GPIO4").unwrap();
+        writer
+            .write_transitions(&[
+                (EdgeKind::Falling, Duration::from_nanos(5_000_000_000)),
+                (EdgeKind::Rising, Duration::from_nanos(5_001_000_000)),
+                (EdgeKind::Falling, Duration::from_nanos(5_001_030_250)),
+            ])
+            .unwrap();
+        // A second reading carries on along the same timeline.
+        writer
+            .write_transitions(&[(EdgeKind::Falling, Duration::from_nanos(7_000_000_000))])
+            .unwrap();
+        assert_eq!(
+            output(writer),
+            format!(
+                "{HEADER}\
+                 #0\n\
+                 $dumpvars 1! $end\n\
+                 #1\n\
+                 0!\n\
+                 #1000001\n\
+                 1!\n\
+                 #1030251\n\
+                 0!\n\
+                 #2000000001\n\
+                 0!\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_simultaneous_transitions() {
+        let mut writer = VcdWriter::new(vec![], "GPIO4").unwrap();
+        let at = Duration::from_secs(1) + Duration::from_micros(10);
+        writer
+            .write_transitions(&[
+                (EdgeKind::Falling, Duration::from_secs(1)),
+                (EdgeKind::Rising, at),
+                (EdgeKind::Falling, at),
+            ])
+            .unwrap();
+        assert!(output(writer).ends_with("$dumpvars 1! $end\n#1\n0!\n#10001\n1!\n#10002\n0!\n"));
+    }
+
+    #[test]
+    fn test_nothing_to_write() {
+        let mut writer = VcdWriter::new(vec![], "GPIO4").unwrap();
+        writer.write_transitions(&[]).unwrap();
+        assert_eq!(output(writer), HEADER);
+    }
+}
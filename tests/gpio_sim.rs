@@ -0,0 +1,245 @@
+//! Tests against real character devices, backed by the `gpio-sim` kernel module. They need root
+//! (or write access to configfs) and the module loaded:
+//!
+//! ```text
+//! sudo modprobe gpio-sim
+//! sudo -E cargo test --features gpio-sim
+//! ```
+//!
+//! Each test creates its own simulated chip through configfs and removes it again when done, so
+//! they can run in parallel alongside whatever chips the machine already has.
+
+#![cfg(feature = "gpio-sim")]
+
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+use dht22_reader::gpiod::{
+    Chip, Direction, Drive, Edge, EdgeKind, Gpiod, IGpiod, LineConfig, LineSettings, Offset,
+    RequestConfig, Value, CONSUMER, DEV_DIR,
+};
+use dht22_reader::pin::{GpiodPin, SensorPin};
+
+const CONFIGFS_DIR: &str = "/sys/kernel/config/gpio-sim";
+const NUM_LINES: usize = 8;
+const SENSOR_OFFSET: Offset = 4;
+
+// Long enough for the kernel to deliver an edge, short enough not to drag out a failing test.
+const EDGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A single-bank `gpio-sim` chip with one named line, torn down on Drop.
+struct SimChip {
+    dir: PathBuf,
+    line_name: String,
+    dev_path: PathBuf,
+    sysfs_dir: PathBuf,
+}
+
+impl SimChip {
+    /// Creates and enables a chip for `test`. The line at [`SENSOR_OFFSET`] gets a name unique to
+    /// the test, so searching every chip for it can only find this one.
+    fn new(test: &str) -> Self {
+        let name = format!("{CONSUMER}-{}-{test}", process::id());
+        let dir = Path::new(CONFIGFS_DIR).join(&name);
+        fs::create_dir(&dir).unwrap_or_else(|e| {
+            panic!(
+                "Failed to create {}: {e} (is gpio-sim loaded, and are we root?)",
+                dir.display()
+            )
+        });
+
+        let bank = dir.join("bank0");
+        fs::create_dir(&bank).unwrap();
+        fs::write(bank.join("label"), &name).unwrap();
+        fs::write(bank.join("num_lines"), NUM_LINES.to_string()).unwrap();
+
+        let line_name = format!("SIM_{}_{test}", process::id()).to_uppercase();
+        let line = bank.join(format!("line{SENSOR_OFFSET}"));
+        fs::create_dir(&line).unwrap();
+        fs::write(line.join("name"), &line_name).unwrap();
+
+        fs::write(dir.join("live"), "1").unwrap();
+
+        let read = |attr: &Path| fs::read_to_string(attr).unwrap().trim().to_string();
+        let dev_name = read(&dir.join("dev_name"));
+        let chip_name = read(&bank.join("chip_name"));
+        SimChip {
+            dir,
+            line_name,
+            dev_path: Path::new(DEV_DIR).join(&chip_name),
+            sysfs_dir: Path::new("/sys/devices/platform")
+                .join(dev_name)
+                .join(chip_name),
+        }
+    }
+
+    fn open(&self) -> Chip {
+        let c_path = CString::new(self.dev_path.as_os_str().as_bytes()).unwrap();
+        Gpiod {}.chip(&c_path).unwrap()
+    }
+
+    /// Pulls the line at `offset` up or down, as the outside world would. An input sees the new
+    /// level straight away.
+    fn set_pull(&self, offset: Offset, up: bool) {
+        let pull = if up { "pull-up" } else { "pull-down" };
+        fs::write(self.line_attr(offset, "pull"), pull).unwrap();
+    }
+
+    /// Returns the level the line at `offset` is at, whoever is driving it.
+    fn level(&self, offset: Offset) -> bool {
+        fs::read_to_string(self.line_attr(offset, "value"))
+            .unwrap()
+            .trim()
+            == "1"
+    }
+
+    fn line_attr(&self, offset: Offset, attr: &str) -> PathBuf {
+        self.sysfs_dir.join(format!("sim_gpio{offset}")).join(attr)
+    }
+}
+
+impl Drop for SimChip {
+    fn drop(&mut self) {
+        // configfs only lets go of a directory once it's empty, and of a bank once it's offline.
+        let bank = self.dir.join("bank0");
+        let _ = fs::write(self.dir.join("live"), "0");
+        let _ = fs::remove_dir(bank.join(format!("line{SENSOR_OFFSET}")));
+        let _ = fs::remove_dir(bank);
+        let _ = fs::remove_dir(&self.dir);
+    }
+}
+
+// Collects edges until the line has been quiet for EDGE_TIMEOUT.
+fn edges(pin: &mut impl SensorPin) -> Vec<EdgeKind> {
+    let mut edges = vec![];
+    loop {
+        let batch = pin.wait_edges(EDGE_TIMEOUT).unwrap();
+        if batch.is_empty() {
+            return edges;
+        }
+        edges.extend(batch.into_iter().map(|e| e.kind));
+    }
+}
+
+#[test]
+fn test_line_names() {
+    let sim = SimChip::new("names");
+    let chip = sim.open();
+    assert_eq!(
+        chip.line_offset_from_name(&sim.line_name).unwrap(),
+        SENSOR_OFFSET
+    );
+    assert_eq!(
+        chip.line_info(SENSOR_OFFSET).unwrap().name(),
+        Some(sim.line_name.clone())
+    );
+    assert_eq!(chip.line_info(0).unwrap().name(), None);
+
+    let found = Gpiod {}
+        .find_line(Path::new(DEV_DIR), &sim.line_name)
+        .unwrap();
+    assert_eq!(found, (sim.dev_path.clone(), SENSOR_OFFSET));
+}
+
+#[test]
+fn test_consumer() {
+    let sim = SimChip::new("consumer");
+    let chip = sim.open();
+    assert!(!chip.line_info(SENSOR_OFFSET).unwrap().is_used());
+
+    let pin = GpiodPin::new(&Gpiod {}, &chip, SENSOR_OFFSET).unwrap();
+    let info = chip.line_info(SENSOR_OFFSET).unwrap();
+    assert!(info.is_used());
+    assert_eq!(info.consumer(), Some(CONSUMER.to_string()));
+
+    drop(pin);
+    assert!(!chip.line_info(SENSOR_OFFSET).unwrap().is_used());
+}
+
+#[test]
+fn test_reconfigure() {
+    let sim = SimChip::new("reconfigure");
+    sim.set_pull(SENSOR_OFFSET, true);
+    let chip = sim.open();
+    let mut pin = GpiodPin::new(&Gpiod {}, &chip, SENSOR_OFFSET).unwrap();
+
+    let info = chip.line_info(SENSOR_OFFSET).unwrap();
+    assert_eq!(info.direction().unwrap(), Direction::Input);
+    assert_eq!(info.edge_detection().unwrap(), Edge::Both);
+
+    pin.drive_low().unwrap();
+    let info = chip.line_info(SENSOR_OFFSET).unwrap();
+    assert_eq!(info.direction().unwrap(), Direction::Output);
+    assert_eq!(info.drive().unwrap(), Drive::OpenDrain);
+    assert!(!sim.level(SENSOR_OFFSET));
+
+    pin.release().unwrap();
+    let info = chip.line_info(SENSOR_OFFSET).unwrap();
+    assert_eq!(info.direction().unwrap(), Direction::Input);
+    assert_eq!(info.edge_detection().unwrap(), Edge::Both);
+    assert!(sim.level(SENSOR_OFFSET));
+}
+
+#[test]
+fn test_values() {
+    let sim = SimChip::new("values");
+    let chip = sim.open();
+    let mut settings = LineSettings::new().unwrap();
+    settings.set_direction(Direction::Input).unwrap();
+    let mut line_cfg = LineConfig::new().unwrap();
+    line_cfg
+        .add_line_settings(&[SENSOR_OFFSET], &settings)
+        .unwrap();
+    let request = Gpiod {}
+        .chip_request_lines(&chip, &RequestConfig::new().unwrap(), &line_cfg)
+        .unwrap();
+
+    sim.set_pull(SENSOR_OFFSET, true);
+    assert_eq!(request.value(SENSOR_OFFSET).unwrap(), Value::Active);
+    sim.set_pull(SENSOR_OFFSET, false);
+    assert_eq!(request.value(SENSOR_OFFSET).unwrap(), Value::Inactive);
+}
+
+#[test]
+fn test_edge_events() {
+    let sim = SimChip::new("edges");
+    sim.set_pull(SENSOR_OFFSET, true);
+    let chip = sim.open();
+    let mut pin = GpiodPin::new(&Gpiod {}, &chip, SENSOR_OFFSET).unwrap();
+    assert!(edges(&mut pin).is_empty());
+
+    sim.set_pull(SENSOR_OFFSET, false);
+    sim.set_pull(SENSOR_OFFSET, true);
+    sim.set_pull(SENSOR_OFFSET, false);
+    assert_eq!(
+        edges(&mut pin),
+        [EdgeKind::Falling, EdgeKind::Rising, EdgeKind::Falling]
+    );
+}
+
+#[test]
+fn test_edge_timestamps() {
+    let sim = SimChip::new("timestamps");
+    sim.set_pull(SENSOR_OFFSET, true);
+    let chip = sim.open();
+    let mut pin = GpiodPin::new(&Gpiod {}, &chip, SENSOR_OFFSET).unwrap();
+
+    sim.set_pull(SENSOR_OFFSET, false);
+    std::thread::sleep(Duration::from_millis(5));
+    sim.set_pull(SENSOR_OFFSET, true);
+
+    let mut edges = vec![];
+    while edges.len() < 2 {
+        let batch = pin.wait_edges(EDGE_TIMEOUT).unwrap();
+        assert!(!batch.is_empty(), "missing edges, only saw {edges:?}");
+        edges.extend(batch);
+    }
+    assert_eq!(edges.len(), 2);
+    assert!(edges[1].timestamp - edges[0].timestamp >= Duration::from_millis(5));
+    assert_eq!(edges[1].seqno, edges[0].seqno + 1);
+    assert_eq!(edges[0].offset, SENSOR_OFFSET);
+}